@@ -1,11 +1,18 @@
-use uart_dap::{Command, Event, Result, UartDap};
+use std::fs::File;
+use std::num::ParseIntError;
+use std::path::PathBuf;
+
+use uart_dap::image::read_image;
+use uart_dap::{
+    Algorithm, Checksum, Command, Credentials, DapHandle, Dialect, Dump, Endianness, Event,
+    ImageFormat, Loader, Result, Target, UartDap, Verifier,
+};
 
 use clap::Parser;
 use futures::StreamExt;
 use tokio::sync::mpsc;
 use tokio_util::codec::{FramedRead, LinesCodec};
 use tracing::{error, info};
-use tracing_subscriber;
 
 #[derive(Parser)]
 #[clap(author, version, about)]
@@ -16,11 +23,97 @@ struct Args {
     #[clap(long, value_enum, default_value_t = ArgLineEnding::CrLf)]
     line_ending: ArgLineEnding,
 
+    #[clap(long, value_enum, default_value_t = ArgTarget::Integrity)]
+    target: ArgTarget,
+
+    /// Byte order of the target memory
+    #[clap(long, value_enum, default_value_t = ArgEndianness::Big)]
+    endianness: ArgEndianness,
+
     #[clap(short, long, default_value_t = 115200)]
     baud_rate: u32,
 
+    /// Log in with the credentials in UART_DAP_USERNAME and UART_DAP_PASSWORD
+    #[clap(long)]
+    login: bool,
+
+    /// Record each write to this file so that `undo` can restore the previous values
+    #[clap(long)]
+    journal: Option<PathBuf>,
+
+    /// Write the commands that would be sent to this file instead of opening the serial port
+    #[clap(long)]
+    dry_run: Option<PathBuf>,
+
     /// Path to serial port device
     path: String,
+
+    #[clap(subcommand)]
+    command: Option<ArgCommand>,
+}
+
+/// Runs a single operation instead of reading commands from stdin
+#[derive(clap::Subcommand)]
+enum ArgCommand {
+    /// Dump a memory range to a file
+    Dump {
+        #[clap(value_parser = parse_addr)]
+        addr: u64,
+
+        #[clap(value_parser = parse_int)]
+        len: u32,
+
+        #[clap(long, value_enum, default_value_t = ArgImageFormat::Binary)]
+        format: ArgImageFormat,
+
+        /// Path to output file
+        output: PathBuf,
+    },
+    /// Load an image file into memory
+    Load {
+        /// Path to image file
+        image: PathBuf,
+
+        /// Format of the image file. Detected from its contents by default.
+        #[clap(long, value_enum)]
+        format: Option<ArgImageFormat>,
+
+        /// Address of the first byte of a binary image
+        #[clap(long, value_parser = parse_addr, default_value = "0")]
+        base: u64,
+
+        /// Read each segment back after writing it
+        #[clap(long)]
+        verify: bool,
+    },
+    /// Compare memory against an image file, failing if any byte differs
+    Verify {
+        /// Path to image file
+        image: PathBuf,
+
+        /// Format of the image file. Detected from its contents by default.
+        #[clap(long, value_enum)]
+        format: Option<ArgImageFormat>,
+
+        /// Address of the first byte of a binary image
+        #[clap(long, value_parser = parse_addr, default_value = "0")]
+        base: u64,
+
+        /// Number of mismatching addresses to report
+        #[clap(long, default_value_t = uart_dap::verify::DEFAULT_MAX_MISMATCHES)]
+        max_mismatches: usize,
+    },
+    /// Print a checksum of a memory range
+    Checksum {
+        #[clap(value_parser = parse_addr)]
+        addr: u64,
+
+        #[clap(value_parser = parse_int)]
+        len: u32,
+
+        #[clap(long, value_enum, default_value_t = ArgAlgorithm::Crc32)]
+        algorithm: ArgAlgorithm,
+    },
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
@@ -36,6 +129,39 @@ enum ArgLineEnding {
     CrLf,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+enum ArgTarget {
+    #[clap(name = "vxworks")]
+    VxWorks,
+    Integrity,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+enum ArgEndianness {
+    Big,
+    Little,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+enum ArgImageFormat {
+    Binary,
+    #[clap(name = "ihex")]
+    IntelHex,
+    #[clap(name = "srec")]
+    SRecord,
+    Hexdump,
+    Elf,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+enum ArgAlgorithm {
+    Crc32,
+    #[clap(name = "crc16-ccitt")]
+    Crc16Ccitt,
+    Sha256,
+    Additive,
+}
+
 impl From<ArgEcho> for uart_dap::Echo {
     fn from(e: ArgEcho) -> Self {
         match e {
@@ -54,6 +180,61 @@ impl From<ArgLineEnding> for uart_dap::LineEnding {
     }
 }
 
+impl From<ArgTarget> for Target {
+    fn from(e: ArgTarget) -> Self {
+        match e {
+            ArgTarget::VxWorks => Self::VxWorks,
+            ArgTarget::Integrity => Self::Integrity,
+        }
+    }
+}
+
+impl From<ArgEndianness> for Endianness {
+    fn from(e: ArgEndianness) -> Self {
+        match e {
+            ArgEndianness::Big => Self::Big,
+            ArgEndianness::Little => Self::Little,
+        }
+    }
+}
+
+impl From<ArgImageFormat> for ImageFormat {
+    fn from(e: ArgImageFormat) -> Self {
+        match e {
+            ArgImageFormat::Binary => Self::Binary,
+            ArgImageFormat::IntelHex => Self::IntelHex,
+            ArgImageFormat::SRecord => Self::SRecord,
+            ArgImageFormat::Hexdump => Self::Hexdump,
+            ArgImageFormat::Elf => Self::Elf,
+        }
+    }
+}
+
+impl From<ArgAlgorithm> for Algorithm {
+    fn from(e: ArgAlgorithm) -> Self {
+        match e {
+            ArgAlgorithm::Crc32 => Self::Crc32,
+            ArgAlgorithm::Crc16Ccitt => Self::Crc16Ccitt,
+            ArgAlgorithm::Sha256 => Self::Sha256,
+            ArgAlgorithm::Additive => Self::Additive,
+        }
+    }
+}
+
+fn parse_int(s: &str) -> std::result::Result<u32, ParseIntError> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+fn parse_addr(s: &str) -> std::result::Result<u64, ParseIntError> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let subscriber = tracing_subscriber::fmt()
@@ -65,21 +246,182 @@ async fn main() -> Result<()> {
     let args = Args::parse();
 
     let (app_command_tx, app_command_rx) = mpsc::channel(1);
-    let (serial_event_tx, serial_event_rx) = mpsc::channel(1);
+    let (serial_event_tx, mut serial_event_rx) = mpsc::channel(1);
 
-    let serial = UartDap::new(
-        &args.path,
-        args.baud_rate,
-        args.echo.into(),
-        args.line_ending.into(),
-    )?;
+    let serial = match args.dry_run {
+        Some(transcript) => UartDap::dry_run(File::create(transcript)?, args.line_ending.into()),
+        None => UartDap::new(
+            &args.path,
+            args.baud_rate,
+            args.echo.into(),
+            args.line_ending.into(),
+        )?,
+    };
+    let mut serial = serial
+        .with_dialect(Dialect::from(Target::from(args.target)))
+        .with_endianness(args.endianness.into());
+    if args.login {
+        serial = serial.with_credentials(Credentials::from_env()?);
+    }
+    if let Some(journal) = args.journal {
+        serial = serial.with_journal(journal);
+    }
 
-    tokio::select! {
-        result = process_commands(app_command_tx) => result,
-        result = serial.run(app_command_rx, serial_event_tx) => result,
-        result = report_events(serial_event_rx) => result,
-    }?;
+    match args.command {
+        None => tokio::select! {
+            result = process_commands(app_command_tx) => result,
+            result = serial.run(app_command_rx, serial_event_tx) => result,
+            result = report_events(serial_event_rx) => result,
+        }?,
+        Some(ArgCommand::Dump {
+            addr,
+            len,
+            format,
+            output,
+        }) => {
+            let dap = serial.handle(&app_command_tx, &mut serial_event_rx);
+            tokio::select! {
+                result = dump(dap, addr, len, format.into(), output) => result,
+                result = serial.run(app_command_rx, serial_event_tx) => result,
+            }?
+        }
+        Some(ArgCommand::Load {
+            image,
+            format,
+            base,
+            verify,
+        }) => {
+            let dap = serial.handle(&app_command_tx, &mut serial_event_rx);
+            tokio::select! {
+                result = load(dap, image, format.map(Into::into), base, verify) => result,
+                result = serial.run(app_command_rx, serial_event_tx) => result,
+            }?
+        }
+        Some(ArgCommand::Verify {
+            image,
+            format,
+            base,
+            max_mismatches,
+        }) => {
+            let dap = serial.handle(&app_command_tx, &mut serial_event_rx);
+            tokio::select! {
+                result = verify(dap, image, format.map(Into::into), base, max_mismatches) => result,
+                result = serial.run(app_command_rx, serial_event_tx) => result,
+            }?
+        }
+        Some(ArgCommand::Checksum {
+            addr,
+            len,
+            algorithm,
+        }) => {
+            let checksum = Checksum::new(algorithm.into(), addr, len);
+            let dap = serial.handle(&app_command_tx, &mut serial_event_rx);
+            tokio::select! {
+                result = checksum_range(dap, checksum) => result,
+                result = serial.run(app_command_rx, serial_event_tx) => result,
+            }?
+        }
+    }
+
+    Ok(())
+}
+
+// Writes whatever was captured even if the dump fails part way
+async fn dump(
+    mut dap: DapHandle<'_>,
+    addr: u64,
+    len: u32,
+    format: ImageFormat,
+    output: PathBuf,
+) -> Result<()> {
+    let mut dump = Dump::new(addr, len);
+    let result = dump
+        .read(&mut dap, |done, total| {
+            info!(done, total, "Dumping");
+        })
+        .await;
+    dump.write(&mut File::create(&output)?, format)?;
+    if let (Err(e), Some(next_addr)) = (&result, dump.next_addr()) {
+        error!(
+            %e,
+            next_addr = format!("{next_addr:#x}").as_str(),
+            "Dump incomplete"
+        );
+    }
+    result
+}
+
+async fn load(
+    mut dap: DapHandle<'_>,
+    image: PathBuf,
+    format: Option<ImageFormat>,
+    base: u64,
+    verify: bool,
+) -> Result<()> {
+    let data = std::fs::read(&image)?;
+    let format = format.unwrap_or_else(|| ImageFormat::detect(&data));
+    let segments = read_image(&data, format, base)?;
+    info!(%format, segments = segments.len(), "Loading image");
+
+    let report = Loader::new()
+        .with_verify(verify)
+        .load(&mut dap, &segments, |done, total| {
+            info!(done, total, "Loading");
+        })
+        .await?;
+    for segment in &report.segments {
+        info!(
+            addr = format!("{:#x}", segment.addr).as_str(),
+            bytes_written = segment.bytes_written,
+            verified = ?segment.verified,
+            "Loaded segment"
+        );
+    }
+    if !report.is_verified() {
+        return Err("read back does not match the image".into());
+    }
+    Ok(())
+}
+
+async fn verify(
+    mut dap: DapHandle<'_>,
+    image: PathBuf,
+    format: Option<ImageFormat>,
+    base: u64,
+    max_mismatches: usize,
+) -> Result<()> {
+    let data = std::fs::read(&image)?;
+    let format = format.unwrap_or_else(|| ImageFormat::detect(&data));
+    let segments = read_image(&data, format, base)?;
+    info!(%format, segments = segments.len(), "Verifying image");
+
+    let report = Verifier::new()
+        .with_max_mismatches(max_mismatches)
+        .verify(&mut dap, &segments, |done, total| {
+            info!(done, total, "Verifying");
+        })
+        .await?;
+    for mismatch in &report.mismatches {
+        println!("{mismatch}");
+    }
+    if !report.is_match() {
+        return Err(format!(
+            "{} of {} bytes do not match the image",
+            report.mismatch_count, report.bytes_compared
+        )
+        .into());
+    }
+    info!(bytes = report.bytes_compared, "Memory matches the image");
+    Ok(())
+}
 
+async fn checksum_range(mut dap: DapHandle<'_>, checksum: Checksum) -> Result<()> {
+    let value = checksum
+        .compute(&mut dap, |done, total| {
+            info!(done, total, "Reading");
+        })
+        .await?;
+    println!("{} {value}", value.algorithm);
     Ok(())
 }
 
@@ -14,7 +14,6 @@ use tokio_stream::StreamExt;
 use tokio_util::codec::{FramedRead, LinesCodec};
 use tokio_util::sync::CancellationToken;
 use tracing::info;
-use tracing_subscriber;
 
 #[derive(Parser)]
 #[clap(author, version, about)]
@@ -127,7 +126,7 @@ where
     S: AsRef<str>,
 {
     let msg = format!("{}{}", msg.as_ref(), line_ending);
-    writer.write(msg.as_bytes()).await?;
+    writer.write_all(msg.as_bytes()).await?;
     info!(msg, "transmited");
     Ok(())
 }
@@ -141,7 +140,7 @@ where
     S: AsRef<str>,
 {
     let msg = msg.as_ref();
-    writer.write(msg.as_bytes()).await?;
+    writer.write_all(msg.as_bytes()).await?;
     info!(msg, "transmited");
     Ok(())
 }
@@ -248,11 +247,11 @@ fn process_request(state: &mut State, req: Request) -> Action {
             .to_string(),
         ),
         ["mw", "kernel", addr, data] => {
-            let addr = match parse_based_int(&addr) {
+            let addr = match parse_based_int(addr) {
                 Ok(value) => value,
                 Err(_) => return Action::Err(format!("unable to parse addr: {}", addr)),
             };
-            let data = match parse_based_int(&data) {
+            let data = match parse_based_int(data) {
                 Ok(value) => value,
                 Err(_) => return Action::Err(format!("unable to parse data: {}", addr)),
             };
@@ -261,11 +260,11 @@ fn process_request(state: &mut State, req: Request) -> Action {
             Action::None
         }
         ["mr", "kernel", addr, nbytes] => {
-            let addr = match parse_based_int(&addr) {
+            let addr = match parse_based_int(addr) {
                 Ok(value) => value,
                 Err(_) => return Action::Err(format!("unable to parse addr: {}", addr)),
             };
-            let nbytes = match parse_based_int(&nbytes) {
+            let nbytes = match parse_based_int(nbytes) {
                 Ok(value) => value,
                 Err(_) => return Action::Err(format!("unable to parse nbytes: {}", nbytes)),
             };
@@ -273,7 +272,7 @@ fn process_request(state: &mut State, req: Request) -> Action {
             process_read_request(state, addr, nbytes)
         }
         ["mr", "kernel", addr] => {
-            let addr = match parse_based_int(&addr) {
+            let addr = match parse_based_int(addr) {
                 Ok(value) => value,
                 Err(_) => return Action::Err(format!("unable to parse addr: {}", addr)),
             };
@@ -284,12 +283,8 @@ fn process_request(state: &mut State, req: Request) -> Action {
     }
 }
 
-fn div_ceil(lhs: u32, rhs: u32) -> u32 {
-    (lhs + rhs - 1) / rhs
-}
-
 fn process_read_request(state: &mut State, addr: u32, nbytes: u32) -> Action {
-    let ndwords = div_ceil(nbytes, 4);
+    let ndwords = nbytes.div_ceil(4);
     let dwords = (0..ndwords).map(|dword_idx| {
         let dword_addr = addr + dword_idx;
         let dword = match state.mem.get(&dword_addr) {
@@ -303,11 +298,31 @@ fn process_read_request(state: &mut State, addr: u32, nbytes: u32) -> Action {
         BigEndian::write_u32(&mut bytes, dword);
         bytes
     });
-    let byte_string = bytes
-        .map(|byte| format!("{byte:x}"))
+    let bytes = bytes.take(nbytes as usize).collect::<Vec<u8>>();
+    let message = bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(line_idx, line_bytes)| {
+            let line_addr = addr + line_idx as u32 * 16;
+            let byte_string = line_bytes
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<String>>()
+                .join(" ");
+            let ascii = line_bytes
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            format!("{line_addr:x}: {byte_string} |{ascii}|")
+        })
         .collect::<Vec<String>>()
-        .join(" ");
-    let message = format!("{addr:x}: {byte_string} |--------|");
+        .join("\r\n");
 
     Action::Respond(message)
 }
@@ -0,0 +1,161 @@
+//! Checksums over ranges of target memory.
+//!
+//! A [`Checksum`] streams the range through a hash a chunk at a time using a [`DapHandle`], so
+//! that the range is never held on the host in full. CRC-32s are instead left to
+//! [`crate::Command::Crc32`], which the target carries out by itself when its dialect offers a
+//! native command.
+
+use std::fmt;
+
+use sha2::{Digest as _, Sha256};
+
+use crate::remote;
+use crate::{DapHandle, Result};
+
+/// Bytes read from the target at a time by default.
+pub const DEFAULT_CHUNK_SIZE: u32 = 256;
+
+/// CRC-16/CCITT-FALSE: polynomial 0x1021 with an initial value of 0xffff.
+static CRC16_CCITT: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_IBM_3740);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Crc32,
+    Crc16Ccitt,
+    Sha256,
+    /// The sum of all bytes modulo 2^32.
+    Additive,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Crc32 => "crc32",
+            Self::Crc16Ccitt => "crc16-ccitt",
+            Self::Sha256 => "sha256",
+            Self::Additive => "additive",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A computed checksum. Displayed as lower case hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumValue {
+    pub algorithm: Algorithm,
+    /// The checksum with the most significant byte first.
+    pub bytes: Vec<u8>,
+}
+
+impl fmt::Display for ChecksumValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.bytes
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+// CRC-32s are computed by `Command::Crc32` rather than on the host here
+enum Hasher {
+    Crc16Ccitt(crc::Digest<'static, u16>),
+    Sha256(Box<Sha256>),
+    Additive(u32),
+}
+
+impl Hasher {
+    fn new(algorithm: Algorithm) -> Option<Self> {
+        let hasher = match algorithm {
+            Algorithm::Crc32 => return None,
+            Algorithm::Crc16Ccitt => Self::Crc16Ccitt(CRC16_CCITT.digest()),
+            Algorithm::Sha256 => Self::Sha256(Box::default()),
+            Algorithm::Additive => Self::Additive(0),
+        };
+        Some(hasher)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Crc16Ccitt(digest) => digest.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Additive(sum) => {
+                *sum = data
+                    .iter()
+                    .fold(*sum, |sum, &byte| sum.wrapping_add(byte.into()))
+            }
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Self::Crc16Ccitt(digest) => digest.finalize().to_be_bytes().to_vec(),
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+            Self::Additive(sum) => sum.to_be_bytes().to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checksum {
+    algorithm: Algorithm,
+    addr: u64,
+    len: u32,
+    chunk_size: u32,
+}
+
+impl Checksum {
+    pub fn new(algorithm: Algorithm, addr: u64, len: u32) -> Self {
+        Self {
+            algorithm,
+            addr,
+            len,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets the number of bytes read at a time. Defaults to [`DEFAULT_CHUNK_SIZE`].
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Computes the checksum, calling `progress` with the bytes hashed so far and the total
+    /// whenever the range is read back to the host.
+    pub async fn compute(
+        &self,
+        dap: &mut DapHandle<'_>,
+        mut progress: impl FnMut(u32, u32),
+    ) -> Result<ChecksumValue> {
+        let algorithm = self.algorithm;
+        let bytes = if let Some(mut hasher) = Hasher::new(algorithm) {
+            let mut done = 0;
+            while done < self.len {
+                let nbytes = (self.len - done).min(self.chunk_size);
+                let data = remote::read_bytes(
+                    dap.command_tx,
+                    dap.event_rx,
+                    self.addr + u64::from(done),
+                    nbytes,
+                    dap.endianness,
+                )
+                .await?;
+                hasher.update(&data);
+                done += nbytes;
+                progress(done, self.len);
+            }
+            hasher.finalize()
+        } else {
+            remote::crc32(
+                dap.command_tx,
+                dap.event_rx,
+                self.addr,
+                self.len,
+                &mut progress,
+            )
+            .await?
+            .to_be_bytes()
+            .to_vec()
+        };
+
+        Ok(ChecksumValue { algorithm, bytes })
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+const BS: char = '\u{8}';
+const DEL: char = '\u{7f}';
+
+/// Counters describing how much of the console stream had to be cleaned up before it could be
+/// interpreted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Statistics {
+    /// Bytes removed from the stream: ANSI escape sequences, backspaces along with the characters
+    /// they erase, and other control characters.
+    pub discarded_bytes: u64,
+    /// Bytes that were not valid UTF-8 and were replaced with U+FFFD.
+    pub corrupted_bytes: u64,
+    /// Read response lines rejected by validation, see [`crate::HexdumpParser::parse_expected`].
+    pub corrupted_responses: u64,
+}
+
+/// Shared, cheaply cloneable view of the statistics of a [`crate::UartDap`].
+///
+/// Obtain one with [`crate::UartDap::statistics`] before calling [`crate::UartDap::run`].
+#[derive(Debug, Clone, Default)]
+pub struct StatisticsHandle {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    discarded_bytes: AtomicU64,
+    corrupted_bytes: AtomicU64,
+    corrupted_responses: AtomicU64,
+}
+
+impl StatisticsHandle {
+    /// Returns a snapshot of the current counter values.
+    pub fn get(&self) -> Statistics {
+        Statistics {
+            discarded_bytes: self.counters.discarded_bytes.load(Ordering::Relaxed),
+            corrupted_bytes: self.counters.corrupted_bytes.load(Ordering::Relaxed),
+            corrupted_responses: self.counters.corrupted_responses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn add_discarded(&self, n: usize) {
+        if n > 0 {
+            self.counters
+                .discarded_bytes
+                .fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn add_corrupted_response(&self) {
+        self.counters
+            .corrupted_responses
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn add_corrupted(&self, n: usize) {
+        if n > 0 {
+            self.counters
+                .corrupted_bytes
+                .fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Decodes a single line of console output into text that is safe to tokenize.
+///
+/// Invalid UTF-8 is replaced with U+FFFD, ANSI escape sequences are removed, backspaces erase the
+/// preceding character, and all other control characters except tab are dropped. Everything
+/// removed or replaced is accounted for in `stats`.
+pub fn decode_line(line: &[u8], stats: &StatisticsHandle) -> String {
+    let (bytes, mut discarded) = strip_escapes(line);
+
+    let mut decoded = String::with_capacity(bytes.len());
+    let mut corrupted = 0;
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                BS | DEL => {
+                    discarded += c.len_utf8();
+                    if let Some(erased) = decoded.pop() {
+                        discarded += erased.len_utf8();
+                    }
+                }
+                // Line endings are expected and are not worth counting
+                '\r' | '\n' => {}
+                '\t' => decoded.push(c),
+                c if c.is_control() => discarded += c.len_utf8(),
+                c => decoded.push(c),
+            }
+        }
+        if !chunk.invalid().is_empty() {
+            corrupted += chunk.invalid().len();
+            decoded.push(char::REPLACEMENT_CHARACTER);
+        }
+    }
+
+    stats.add_discarded(discarded);
+    stats.add_corrupted(corrupted);
+
+    decoded
+}
+
+// Removes CSI (`ESC [ ... final`), OSC (`ESC ] ... BEL` or `ESC ] ... ESC \`), and two byte
+// escape sequences. Returns the remaining bytes and the number of bytes removed.
+fn strip_escapes(line: &[u8]) -> (Vec<u8>, usize) {
+    let mut stripped = Vec::with_capacity(line.len());
+    let mut idx = 0;
+
+    while idx < line.len() {
+        if line[idx] != ESC {
+            stripped.push(line[idx]);
+            idx += 1;
+            continue;
+        }
+
+        let len = match line.get(idx + 1) {
+            Some(b'[') => line[idx + 2..]
+                .iter()
+                .position(|b| (0x40..=0x7e).contains(b))
+                .map_or(line.len() - idx, |pos| pos + 3),
+            Some(b']') => {
+                let body = &line[idx + 2..];
+                (0..body.len())
+                    .find_map(|pos| match (body[pos], body.get(pos + 1)) {
+                        (BEL, _) => Some(pos + 3),
+                        (ESC, Some(b'\\')) => Some(pos + 4),
+                        _ => None,
+                    })
+                    .unwrap_or(line.len() - idx)
+            }
+            Some(_) => 2,
+            None => 1,
+        };
+        idx += len;
+    }
+
+    let discarded = line.len() - stripped.len();
+    (stripped, discarded)
+}
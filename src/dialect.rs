@@ -0,0 +1,359 @@
+use crate::{
+    format_write_value, parse_based_int, parse_space, AddressSpace, Command, Endianness, Target,
+    Width,
+};
+
+/// How the target writes a block of consecutive words.
+///
+/// The presets all use [`BlockWrite::Single`], as multi-value `mw` depends on the shell build;
+/// set [`BlockWrite::MultiValue`] on a [`Dialect`] whose shell is known to accept it. The
+/// interactive `m` command of VxWorks is not supported, so VxWorks blocks are written one `mw`
+/// per word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockWrite {
+    /// One `mw` per word.
+    Single,
+    /// Up to `max_values` words per `mw`, as in `mw <space> <addr> <data> <data> ...`.
+    MultiValue { max_values: usize },
+}
+
+/// The memory command syntax a shell accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// `mr <space> <addr> <nbytes>` and `mw <space> <addr> <data>`, where `<space>` is an
+    /// [`AddressSpace`] such as `kernel`. `mw` writes a word, or a doubleword when `<data>` has
+    /// sixteen hex digits. There are no byte or halfword writes.
+    Kernel,
+    /// `md.b <addr> <count>`, `mw.{b,w,l,q} <addr> <data> [count]`, `cp.b <src> <dst> <count>`, and
+    /// `crc32 <addr> <count>`. There are no address spaces, so the [`AddressSpace`] of every
+    /// command is ignored.
+    UBoot,
+}
+
+/// Describes how a target shell presents itself on the console.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dialect {
+    /// The first token of the line on which the shell accepts a command.
+    pub prompt: String,
+    /// Text printed by the shell when it pauses long output until a key is pressed.
+    pub pager_prompts: Vec<String>,
+    /// The keystroke sent to continue past a pager prompt.
+    pub pager_continue: String,
+    /// Text ending the line on which the target asks for a username.
+    pub login_prompts: Vec<String>,
+    /// Text ending the line on which the target asks for a password.
+    pub password_prompts: Vec<String>,
+    /// The memory command syntax.
+    pub syntax: Syntax,
+    /// The most efficient way the shell offers to write a block of words.
+    /// [`Command::WriteBlock`] is broken into single writes unless this is
+    /// [`BlockWrite::MultiValue`].
+    pub block_write: BlockWrite,
+    /// The least number of hex digits addresses are printed with in commands, such as 16 for
+    /// shells that expect full width 64-bit addresses. Shorter addresses are padded with zeros.
+    pub address_digits: usize,
+}
+
+impl Dialect {
+    /// Green Hills Integrity debug console.
+    pub fn integrity() -> Self {
+        Self {
+            prompt: "DEBUG>".to_string(),
+            pager_prompts: vec!["--More--".to_string(), "Press any key".to_string()],
+            pager_continue: " ".to_string(),
+            login_prompts: vec!["login:".to_string()],
+            password_prompts: vec!["Password:".to_string()],
+            syntax: Syntax::Kernel,
+            block_write: BlockWrite::Single,
+            address_digits: 0,
+        }
+    }
+
+    /// Wind River VxWorks kernel shell.
+    pub fn vxworks() -> Self {
+        Self {
+            prompt: "->".to_string(),
+            pager_prompts: Vec::new(),
+            pager_continue: " ".to_string(),
+            login_prompts: vec!["login:".to_string()],
+            password_prompts: vec!["Password:".to_string()],
+            syntax: Syntax::Kernel,
+            block_write: BlockWrite::Single,
+            address_digits: 0,
+        }
+    }
+
+    /// Das U-Boot command line. Fills, copies, and CRC-32s are carried out by the target.
+    pub fn uboot() -> Self {
+        Self {
+            prompt: "=>".to_string(),
+            pager_prompts: Vec::new(),
+            pager_continue: " ".to_string(),
+            login_prompts: Vec::new(),
+            password_prompts: Vec::new(),
+            syntax: Syntax::UBoot,
+            block_write: BlockWrite::Single,
+            address_digits: 0,
+        }
+    }
+
+    /// Returns true if the shell can carry out `command` by itself. Commands it cannot are
+    /// broken down into reads and writes on the host.
+    pub fn is_native(&self, command: &Command) -> bool {
+        match command {
+            Command::Read { .. } => true,
+            Command::Write { width, .. } => {
+                self.syntax == Syntax::UBoot || matches!(width, Width::Word | Width::Doubleword)
+            }
+            Command::WriteBlock { .. } => matches!(self.block_write, BlockWrite::MultiValue { .. }),
+            Command::Fill {
+                addr, len, width, ..
+            } => {
+                self.syntax == Syntax::UBoot
+                    && addr.is_multiple_of(width.bytes().into())
+                    && len.is_multiple_of(width.bytes())
+            }
+            Command::Copy { .. } | Command::Crc32 { .. } => self.syntax == Syntax::UBoot,
+            Command::Modify { .. }
+            | Command::Undo { .. }
+            | Command::Search { .. }
+            | Command::WaitFor { .. }
+            | Command::Watch { .. }
+            | Command::Unwatch { .. } => false,
+        }
+    }
+
+    /// Returns true if commands name the [`AddressSpace`] they access.
+    pub(crate) fn has_address_spaces(&self) -> bool {
+        self.syntax == Syntax::Kernel
+    }
+
+    /// Returns true if the shell reads exactly the bytes asked for at any address, as U-Boot
+    /// `md.b` does, so that unaligned reads need not be widened.
+    pub(crate) fn has_narrow_reads(&self) -> bool {
+        self.syntax == Syntax::UBoot
+    }
+
+    /// Returns the byte offset of the first pager prompt found in `text`.
+    pub(crate) fn find_pager_prompt(&self, text: &[u8]) -> Option<usize> {
+        self.pager_prompts
+            .iter()
+            .filter(|pager| !pager.is_empty())
+            .filter_map(|pager| {
+                text.windows(pager.len())
+                    .position(|window| window == pager.as_bytes())
+            })
+            .min()
+    }
+
+    /// Returns the text sent to the target for `command`.
+    ///
+    /// Words are formed from data bytes using `endianness`.
+    pub fn format_command(&self, command: &Command, endianness: Endianness) -> String {
+        match (self.syntax, command) {
+            (Syntax::UBoot, Command::Read { addr, nbytes, .. }) => {
+                format!("md.b {} {nbytes:#x}", self.format_addr(*addr))
+            }
+            (
+                Syntax::UBoot,
+                Command::Write {
+                    addr, data, width, ..
+                },
+            ) => {
+                let suffix = uboot_suffix(*width);
+                let data = width.truncate(*data);
+                format!("mw.{suffix} {} {data:#x}", self.format_addr(*addr))
+            }
+            (
+                Syntax::UBoot,
+                Command::Fill {
+                    addr,
+                    len,
+                    pattern,
+                    width,
+                    ..
+                },
+            ) => {
+                let suffix = uboot_suffix(*width);
+                let count = len / width.bytes();
+                let addr = self.format_addr(*addr);
+                format!("mw.{suffix} {addr} {pattern:#x} {count:#x}")
+            }
+            (Syntax::UBoot, Command::Copy { src, dst, len, .. }) => {
+                let (src, dst) = (self.format_addr(*src), self.format_addr(*dst));
+                format!("cp.b {src} {dst} {len:#x}")
+            }
+            (Syntax::UBoot, Command::Crc32 { addr, len, .. }) => {
+                format!("crc32 {} {len:#x}", self.format_addr(*addr))
+            }
+            (
+                Syntax::Kernel,
+                Command::Read {
+                    space,
+                    addr,
+                    nbytes,
+                },
+            ) => format!("mr {space} {} {nbytes}", self.format_addr(*addr)),
+            (
+                Syntax::Kernel,
+                Command::Write {
+                    space,
+                    addr,
+                    data,
+                    width: width @ (Width::Word | Width::Doubleword),
+                },
+            ) => {
+                let data = format_write_value(*data, *width);
+                format!("mw {space} {} {data}", self.format_addr(*addr))
+            }
+            (_, Command::WriteBlock { space, addr, data }) => {
+                let values = data
+                    .chunks(4)
+                    .map(|word| format!("{:#x}", endianness.word_from_bytes(word)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("mw {space} {} {values}", self.format_addr(*addr))
+            }
+            (_, command) => command.to_string(),
+        }
+    }
+
+    /// Returns `addr` in hex as it appears in commands sent to the target.
+    pub fn format_addr(&self, addr: u64) -> String {
+        format!("{addr:#0width$x}", width = self.address_digits + 2)
+    }
+
+    /// Parses a command as echoed by the target. The inverse of [`Dialect::format_command`].
+    pub fn parse_command(&self, tokens: &[&str], endianness: Endianness) -> Option<Command> {
+        if self.syntax == Syntax::UBoot {
+            return parse_uboot_command(tokens);
+        }
+
+        match tokens {
+            ["mw", space, addr, values @ ..] if values.len() > 1 => {
+                let space = parse_space(space)?;
+                let addr = parse_based_int(addr).ok()?;
+                let data = values
+                    .iter()
+                    .map(|value| {
+                        parse_based_int(value).map(|word| endianness.word_to_bytes(word, 4))
+                    })
+                    .collect::<crate::Result<Vec<_>>>()
+                    .ok()?
+                    .concat();
+                Some(Command::WriteBlock { space, addr, data })
+            }
+            // The other verbs of `Command::from_tokens` are carried out by the executor and never
+            // reach the shell
+            ["mr" | "mw", ..] => Command::from_tokens(tokens),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_login_prompt(&self, partial_line: &str) -> bool {
+        ends_with_any(partial_line, &self.login_prompts)
+    }
+
+    pub(crate) fn is_password_prompt(&self, partial_line: &str) -> bool {
+        ends_with_any(partial_line, &self.password_prompts)
+    }
+
+    /// Removes every pager prompt from `line`.
+    pub(crate) fn strip_pager_prompts(&self, line: &str) -> String {
+        self.pager_prompts
+            .iter()
+            .fold(line.to_string(), |line, pager| {
+                line.replace(pager.as_str(), "")
+            })
+    }
+}
+
+fn uboot_suffix(width: Width) -> char {
+    match width {
+        Width::Byte => 'b',
+        Width::Halfword => 'w',
+        Width::Word => 'l',
+        Width::Doubleword => 'q',
+    }
+}
+
+// U-Boot counts are hexadecimal even without a `0x` prefix
+fn parse_uboot_int(s: &str) -> Option<u64> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    u64::from_str_radix(s, 16).ok()
+}
+
+// The bytes covered by `count` elements of `width`
+fn byte_len(count: u64, width: Width) -> Option<u32> {
+    u32::try_from(count).ok()?.checked_mul(width.bytes())
+}
+
+fn parse_uboot_command(tokens: &[&str]) -> Option<Command> {
+    let (name, args) = tokens.split_first()?;
+    let args = args
+        .iter()
+        .map(|arg| parse_uboot_int(arg))
+        .collect::<Option<Vec<_>>>()?;
+    let (name, suffix) = name.split_once('.').unwrap_or((name, "l"));
+    let width = match suffix {
+        "b" => Width::Byte,
+        "w" => Width::Halfword,
+        "l" => Width::Word,
+        "q" => Width::Doubleword,
+        _ => return None,
+    };
+
+    match (name, args.as_slice()) {
+        ("md", &[addr, count]) => Some(Command::Read {
+            space: AddressSpace::kernel(),
+            addr,
+            nbytes: byte_len(count, width)?,
+        }),
+        ("mw", &[addr, data]) => Some(Command::Write {
+            space: AddressSpace::kernel(),
+            addr,
+            data,
+            width,
+        }),
+        ("mw", &[addr, pattern, count]) => Some(Command::Fill {
+            space: AddressSpace::kernel(),
+            addr,
+            len: byte_len(count, width)?,
+            pattern,
+            width,
+        }),
+        ("cp", &[src, dst, count]) => Some(Command::Copy {
+            space: AddressSpace::kernel(),
+            src,
+            dst,
+            len: byte_len(count, width)?,
+        }),
+        ("crc32", &[addr, len]) => Some(Command::Crc32 {
+            space: AddressSpace::kernel(),
+            addr,
+            len: len.try_into().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self::integrity()
+    }
+}
+
+impl From<Target> for Dialect {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::VxWorks => Self::vxworks(),
+            Target::Integrity => Self::integrity(),
+        }
+    }
+}
+
+fn ends_with_any(text: &str, patterns: &[String]) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| !pattern.is_empty() && text.ends_with(pattern.as_str()))
+}
@@ -0,0 +1,88 @@
+//! Stands in for the serial port and the target when the DAP runs without either.
+//!
+//! Commands are checked and formatted exactly as they would be for the console and recorded in a
+//! transcript, and the response the target would have given is made up so that the operation
+//! carries on to the end.
+
+use std::io::Write;
+
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::{Command, Event, LineEnding, Result, Splitter};
+
+#[tracing::instrument(name = "dry_run", skip_all)]
+pub(crate) async fn transmitter(
+    splitter: Splitter<'_>,
+    line_ending: LineEnding,
+    mut command_rx: mpsc::Receiver<Command>,
+    response_tx: mpsc::Sender<Event>,
+    mut transcript: Box<dyn Write + Send>,
+) -> Result<()> {
+    while let Some(command) = command_rx.recv().await {
+        let Some(command) = splitter.admit(command, &response_tx).await? else {
+            continue;
+        };
+
+        let text = splitter
+            .dialect
+            .format_command(&command, splitter.endianness);
+        info!(text, "Recording command");
+        write!(transcript, "{text}{line_ending}")?;
+        transcript.flush()?;
+
+        for event in responses(command) {
+            response_tx.send(event).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// The events the console would produce if the target carried out `command`. Reads return zeros,
+// except that reads past the top of the address space fail.
+fn responses(command: Command) -> Vec<Event> {
+    match command {
+        Command::Read {
+            space,
+            addr,
+            nbytes,
+        } => match addr.checked_add(u64::from(nbytes)) {
+            Some(end) => (addr..end)
+                .step_by(4)
+                .map(|addr| Event::Read {
+                    space: space.clone(),
+                    addr,
+                    data: 0,
+                })
+                .collect(),
+            None => vec![Event::ReadError { addr, nbytes }],
+        },
+        Command::Write {
+            space,
+            addr,
+            data,
+            width,
+        } => vec![Event::Write {
+            space,
+            addr,
+            data,
+            width,
+        }],
+        Command::WriteBlock { space, addr, data } => vec![Event::WriteBlock {
+            space,
+            addr,
+            len: data.len() as u32,
+        }],
+        Command::Fill { addr, len, .. } => vec![Event::Fill { addr, len }],
+        Command::Copy { src, dst, len, .. } => vec![Event::Copy { src, dst, len }],
+        Command::Crc32 { addr, len, .. } => vec![Event::Crc32 { addr, len, crc: 0 }],
+        // Carried out by the executor and never sent
+        Command::Modify { .. }
+        | Command::WaitFor { .. }
+        | Command::Watch { .. }
+        | Command::Unwatch { .. }
+        | Command::Undo { .. }
+        | Command::Search { .. } => Vec::new(),
+    }
+}
@@ -0,0 +1,104 @@
+//! Capture of target memory ranges for offline analysis.
+//!
+//! A [`Dump`] reads a range through a [`DapHandle`] in chunks aligned to the chunk size, so that
+//! every read but the first and last covers whole response lines. If a chunk cannot be read the
+//! dump stops with the bytes captured so far kept, and [`Dump::read`] may be called again to
+//! resume from where it stopped.
+
+use std::io::Write;
+
+use tracing::info;
+
+use crate::image::{write_image, ImageFormat, Segment};
+use crate::remote;
+use crate::{DapHandle, Result};
+
+/// Bytes requested by each read unless configured otherwise.
+pub const DEFAULT_CHUNK_SIZE: u32 = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dump {
+    addr: u64,
+    len: u32,
+    data: Vec<u8>,
+    chunk_size: u32,
+}
+
+impl Dump {
+    /// Dumps the `len` bytes starting at `addr`.
+    pub fn new(addr: u64, len: u32) -> Self {
+        Self {
+            addr,
+            len,
+            data: Vec::with_capacity(len as usize),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets the bytes requested by each read. Defaults to [`DEFAULT_CHUNK_SIZE`].
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// The bytes captured so far.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The address of the first byte not captured yet, or `None` once the whole range is
+    /// captured.
+    pub fn next_addr(&self) -> Option<u64> {
+        match self.is_complete() {
+            true => None,
+            false => Some(self.addr + self.data.len() as u64),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.data.len() as u32 == self.len
+    }
+
+    /// Reads the rest of the range, calling `progress` with the bytes captured and the total
+    /// after each chunk.
+    ///
+    /// Fails at the first chunk that cannot be read. Whatever was captured before the failure is
+    /// kept and calling this again resumes from [`Dump::next_addr`].
+    pub async fn read(
+        &mut self,
+        dap: &mut DapHandle<'_>,
+        mut progress: impl FnMut(u32, u32),
+    ) -> Result<()> {
+        while let Some(addr) = self.next_addr() {
+            // Counted from the start of the chunk so that a range ending at the top of the
+            // address space does not overflow
+            let to_chunk_end = self.chunk_size - (addr % u64::from(self.chunk_size)) as u32;
+            let nbytes = to_chunk_end.min(self.len - self.data.len() as u32);
+
+            let failed = remote::read_into(
+                dap.command_tx,
+                dap.event_rx,
+                addr,
+                nbytes,
+                dap.endianness,
+                &mut self.data,
+            )
+            .await?;
+            if let Some(error_addr) = failed {
+                return Err(format!("could not read memory at {error_addr:#x}").into());
+            }
+            progress(self.data.len() as u32, self.len);
+        }
+
+        info!(addr = self.addr, len = self.len, "Dump complete");
+        Ok(())
+    }
+
+    /// Writes the bytes captured so far to `writer` in `format`.
+    pub fn write(&self, writer: &mut impl Write, format: ImageFormat) -> Result<()> {
+        let segment = Segment::new(self.addr, self.data.clone());
+        write_image(writer, format, &[segment])?;
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,856 @@
+//! Carries out application commands one at a time.
+//!
+//! Reads and writes are passed through to the target. Everything else is expanded into reads and
+//! writes whose responses are consumed here instead of being passed on to the application.
+
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+use tracing::{info, warn};
+
+use crate::journal::{Entry, Journal};
+use crate::{
+    AddressSpace, Alignment, BlockWrite, Command, Dialect, Endianness, Error, Event, Result,
+    Violation, Width,
+};
+
+/// The most bytes held on the host at once while copying or computing a CRC.
+const COPY_CHUNK_SIZE: u32 = 256;
+
+/// CRC-32 as used by IEEE 802.3, zlib, and U-Boot.
+static CRC32: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Why an operation stopped before completing.
+pub(crate) enum Failure {
+    /// The operation was abandoned and the event tells the application why.
+    Event(Event),
+    /// The DAP can no longer run.
+    Fatal(Error),
+}
+
+impl<E: Into<Error>> From<E> for Failure {
+    fn from(error: E) -> Self {
+        Self::Fatal(error.into())
+    }
+}
+
+pub(crate) type Outcome<T> = std::result::Result<T, Failure>;
+
+/// How much of an operation spanning several commands has completed.
+pub(crate) struct Progress {
+    done: u32,
+    total: u32,
+}
+
+impl Progress {
+    pub(crate) fn new(total: u32) -> Self {
+        Self { done: 0, total }
+    }
+}
+
+/// A range of memory polled for changes in between other commands.
+struct Watch {
+    space: AddressSpace,
+    addr: u64,
+    nbytes: u32,
+    period: Duration,
+    due: Instant,
+    /// The bytes from the previous poll
+    last: Option<Vec<u8>>,
+}
+
+pub(crate) struct Executor {
+    dialect: Dialect,
+    endianness: Endianness,
+    alignment: Alignment,
+    journal: Option<Journal>,
+    command_tx: mpsc::Sender<Command>,
+    response_rx: mpsc::Receiver<Event>,
+    event_tx: mpsc::Sender<Event>,
+    watches: Vec<Watch>,
+}
+
+impl Executor {
+    pub(crate) fn new(
+        dialect: Dialect,
+        endianness: Endianness,
+        alignment: Alignment,
+        journal: Option<Journal>,
+        command_tx: mpsc::Sender<Command>,
+        response_rx: mpsc::Receiver<Event>,
+        event_tx: mpsc::Sender<Event>,
+    ) -> Self {
+        Self {
+            dialect,
+            endianness,
+            alignment,
+            journal,
+            command_tx,
+            response_rx,
+            event_tx,
+            watches: Vec::new(),
+        }
+    }
+
+    #[tracing::instrument(name = "command_executor", skip_all)]
+    pub(crate) async fn run(mut self, mut app_command_rx: mpsc::Receiver<Command>) -> Result<()> {
+        loop {
+            let event = match self.step(&mut app_command_rx).await {
+                None => return Ok(()),
+                Some(Ok(())) => continue,
+                Some(Err(Failure::Event(event))) => event,
+                Some(Err(Failure::Fatal(error))) => return Err(error),
+            };
+            self.send_event(event).await?;
+        }
+    }
+
+    // Executes the next command or polls the watches that are due, whichever comes first. Returns
+    // `None` once there are no more commands.
+    async fn step(&mut self, app_command_rx: &mut mpsc::Receiver<Command>) -> Option<Outcome<()>> {
+        let due = self.watches.iter().map(|watch| watch.due).min();
+        tokio::select! {
+            command = app_command_rx.recv() => {
+                let command = command?;
+                info!(?command, "Executing command");
+                Some(self.execute(command).await)
+            }
+            _ = time::sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                Some(self.poll_watches().await)
+            }
+        }
+    }
+
+    async fn execute(&mut self, command: Command) -> Outcome<()> {
+        match command {
+            Command::Read {
+                space,
+                addr,
+                nbytes,
+            } => {
+                self.read_with(&space, addr, nbytes, true).await?;
+            }
+            Command::Write {
+                space,
+                addr,
+                data,
+                width,
+            } => {
+                let old = match self.journal {
+                    Some(_) => {
+                        let bytes = self.read_with(&space, addr, width.bytes(), false).await?;
+                        Some(self.endianness.value_from_bytes(&bytes))
+                    }
+                    None => None,
+                };
+                self.write(&space, addr, data, width).await?;
+                if let Some(old) = old {
+                    self.record_write(Entry {
+                        space: space.clone(),
+                        addr,
+                        width,
+                        old,
+                        new: width.truncate(data),
+                    })?;
+                }
+                let event = Event::Write {
+                    space,
+                    addr,
+                    data,
+                    width,
+                };
+                self.send_event(event).await?;
+            }
+            Command::WriteBlock { space, addr, data } => {
+                let len = data.len() as u32;
+                self.write_block(&space, addr, &data, &mut Progress::new(len))
+                    .await?;
+                self.send_event(Event::WriteBlock { space, addr, len })
+                    .await?;
+            }
+            Command::Fill { addr, len, .. } if self.dialect.is_native(&command) => {
+                let event = Event::Fill { addr, len };
+                self.native(command, &event).await?;
+                self.send_event(event).await?;
+            }
+            Command::Fill {
+                space,
+                addr,
+                len,
+                pattern,
+                width,
+            } => {
+                let element = self
+                    .endianness
+                    .value_to_bytes(pattern, width.bytes() as usize);
+                let data = element
+                    .iter()
+                    .copied()
+                    .cycle()
+                    .take(len as usize)
+                    .collect::<Vec<_>>();
+                self.write_block(&space, addr, &data, &mut Progress::new(len))
+                    .await?;
+                self.send_event(Event::Fill { addr, len }).await?;
+            }
+            Command::Copy { src, dst, len, .. } if self.dialect.is_native(&command) => {
+                let event = Event::Copy { src, dst, len };
+                self.native(command, &event).await?;
+                self.send_event(event).await?;
+            }
+            Command::Copy {
+                space,
+                src,
+                dst,
+                len,
+            } => {
+                self.copy(&space, src, dst, len).await?;
+                self.send_event(Event::Copy { src, dst, len }).await?;
+            }
+            Command::Crc32 { addr, len, .. } if self.dialect.is_native(&command) => {
+                let crc = self.native_crc32(command).await?;
+                self.send_event(Event::Crc32 { addr, len, crc }).await?;
+            }
+            Command::Crc32 { space, addr, len } => {
+                let crc = self.crc32(&space, addr, len).await?;
+                self.send_event(Event::Crc32 { addr, len, crc }).await?;
+            }
+            Command::Undo { count } => {
+                let mut undone = 0;
+                while count.is_none_or(|count| undone < count) {
+                    let Some(entry) = self.journal.as_ref().and_then(Journal::last).cloned() else {
+                        break;
+                    };
+                    info!(?entry, "Undoing write");
+                    self.write(&entry.space, entry.addr, entry.old, entry.width)
+                        .await?;
+                    if let Some(journal) = &mut self.journal {
+                        journal.record_undo()?;
+                    }
+                    undone += 1;
+                }
+                self.send_event(Event::Undo { count: undone }).await?;
+            }
+            Command::Search { addr, len, .. } => {
+                let matches = self.search(&command).await?;
+                self.send_event(Event::Search { addr, len, matches })
+                    .await?;
+            }
+            Command::Modify {
+                space,
+                addr,
+                mask,
+                value,
+            } => {
+                let old = self.read_word(&space, addr).await?;
+                let new = (old & !mask) | (value & mask);
+                self.write(&space, addr, new.into(), Width::Word).await?;
+                self.record_write(Entry {
+                    space,
+                    addr,
+                    width: Width::Word,
+                    old: old.into(),
+                    new: new.into(),
+                })?;
+                self.send_event(Event::Modify { addr, old, new }).await?;
+            }
+            Command::Watch {
+                space,
+                addr,
+                nbytes,
+                period,
+            } => {
+                self.watches
+                    .retain(|watch| (&watch.space, watch.addr) != (&space, addr));
+                self.watches.push(Watch {
+                    space,
+                    addr,
+                    nbytes,
+                    period,
+                    due: Instant::now(),
+                    last: None,
+                });
+            }
+            Command::Unwatch { space, addr } => {
+                self.watches
+                    .retain(|watch| (&watch.space, watch.addr) != (&space, addr));
+            }
+            Command::WaitFor {
+                space,
+                addr,
+                mask,
+                expected,
+                interval,
+                timeout,
+            } => {
+                let deadline = Instant::now() + timeout;
+                loop {
+                    let value = self.read_word(&space, addr).await?;
+                    if value & mask == expected & mask {
+                        self.send_event(Event::WaitFor { addr, value }).await?;
+                        break;
+                    }
+                    if Instant::now() + interval > deadline {
+                        return Err(Failure::Event(Event::WaitTimeout { addr, value }));
+                    }
+                    time::sleep(interval).await;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads `nbytes` starting at `addr` in `space` and returns them in memory order.
+    pub(crate) async fn read(
+        &mut self,
+        space: &AddressSpace,
+        addr: u64,
+        nbytes: u32,
+    ) -> Outcome<Vec<u8>> {
+        self.read_with(space, addr, nbytes, false).await
+    }
+
+    // Polls every watch that is due, reporting words that changed since the previous poll
+    async fn poll_watches(&mut self) -> Outcome<()> {
+        let now = Instant::now();
+        for idx in 0..self.watches.len() {
+            let Watch {
+                ref space,
+                addr,
+                nbytes,
+                period,
+                due,
+                ..
+            } = self.watches[idx];
+            if due > now {
+                continue;
+            }
+            let space = space.clone();
+            // Skip polls that were missed rather than catching up on them
+            self.watches[idx].due = (due + period).max(now);
+
+            let bytes = self.read(&space, addr, nbytes).await?;
+            let timestamp = SystemTime::now();
+            let last = self.watches[idx].last.replace(bytes.clone());
+            let Some(last) = last else {
+                for (offset, word) in bytes.chunks(4).enumerate() {
+                    let event = Event::Watched {
+                        space: space.clone(),
+                        addr: addr + offset as u64 * 4,
+                        value: self.endianness.word_from_bytes(word),
+                        timestamp,
+                    };
+                    self.send_event(event).await?;
+                }
+                continue;
+            };
+            for (offset, (old, new)) in last.chunks(4).zip(bytes.chunks(4)).enumerate() {
+                if old == new {
+                    continue;
+                }
+                let event = Event::Changed {
+                    space: space.clone(),
+                    addr: addr + offset as u64 * 4,
+                    old: self.endianness.word_from_bytes(old),
+                    new: self.endianness.word_from_bytes(new),
+                    timestamp,
+                };
+                self.send_event(event).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn read_word(&mut self, space: &AddressSpace, addr: u64) -> Outcome<u32> {
+        let bytes = self.read(space, addr, 4).await?;
+        Ok(self.endianness.word_from_bytes(&bytes))
+    }
+
+    // Optionally passes each read event on to the application. Reads that do not start on a word
+    // boundary are widened to start on one when splitting on a dialect without narrow reads, and
+    // the events describe only the bytes asked for.
+    async fn read_with(
+        &mut self,
+        space: &AddressSpace,
+        addr: u64,
+        nbytes: u32,
+        forward: bool,
+    ) -> Outcome<Vec<u8>> {
+        let offset = addr % 4;
+        if offset == 0 || nbytes == 0 {
+            return self.read_aligned(space, addr, nbytes, forward).await;
+        }
+
+        match self.alignment {
+            Alignment::Unchecked => self.read_aligned(space, addr, nbytes, forward).await,
+            Alignment::Split if self.dialect.has_narrow_reads() => {
+                self.read_aligned(space, addr, nbytes, forward).await
+            }
+            Alignment::Reject => {
+                let space = space.clone();
+                let command = Command::Read {
+                    space,
+                    addr,
+                    nbytes,
+                };
+                Err(unaligned(command, addr, Width::Word))
+            }
+            Alignment::Split => {
+                let bytes = self
+                    .read_aligned(space, addr - offset, nbytes + offset as u32, false)
+                    .await?
+                    .split_off(offset as usize);
+                if forward {
+                    for (addr, word) in (addr..).step_by(4).zip(bytes.chunks(4)) {
+                        let data = self.endianness.word_from_bytes(word).into();
+                        let space = space.clone();
+                        self.send_event(Event::Read { space, addr, data }).await?;
+                    }
+                }
+                Ok(bytes)
+            }
+        }
+    }
+
+    // Passes each read event on to the application as it arrives if `forward`
+    async fn read_aligned(
+        &mut self,
+        space: &AddressSpace,
+        addr: u64,
+        nbytes: u32,
+        forward: bool,
+    ) -> Outcome<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(nbytes as usize);
+        if nbytes == 0 {
+            return Ok(bytes);
+        }
+
+        let command = Command::Read {
+            space: space.clone(),
+            addr,
+            nbytes,
+        };
+        self.command_tx.send(command).await?;
+
+        while (bytes.len() as u32) < nbytes {
+            let response = self.response().await?;
+            match response {
+                Event::Read {
+                    space: ref data_space,
+                    addr: data_addr,
+                    data,
+                } if self.is_space(data_space, space) && data_addr == addr + bytes.len() as u64 => {
+                    let len = (nbytes as usize - bytes.len()).min(4);
+                    bytes.extend(self.endianness.value_to_bytes(data, len));
+                    if forward {
+                        let space = space.clone();
+                        let event = Event::Read {
+                            space,
+                            addr: data_addr,
+                            data,
+                        };
+                        self.send_event(event).await?;
+                    }
+                }
+                event @ Event::ReadError { .. } => return Err(Failure::Event(event)),
+                event => warn!(?event, "Ignoring unexpected response"),
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    /// Writes the low `width` bytes of `data` at `addr`. Narrow writes the dialect has no command
+    /// for are merged into the words around them only under [`Alignment::Split`].
+    pub(crate) async fn write(
+        &mut self,
+        space: &AddressSpace,
+        addr: u64,
+        data: u64,
+        width: Width,
+    ) -> Outcome<()> {
+        let command = Command::Write {
+            space: space.clone(),
+            addr,
+            data,
+            width,
+        };
+        let split = self.alignment == Alignment::Split;
+        if !self.dialect.is_native(&command) {
+            if !split {
+                return Err(Failure::Event(Event::Unsupported { command }));
+            }
+            let bytes = self.endianness.value_to_bytes(data, width.bytes() as usize);
+            return self.write_narrow(space, addr, &bytes).await;
+        }
+        if !addr.is_multiple_of(width.bytes().into()) {
+            match self.alignment {
+                Alignment::Unchecked => {}
+                Alignment::Reject => return Err(unaligned(command, addr, width)),
+                Alignment::Split => {
+                    let bytes = self.endianness.value_to_bytes(data, width.bytes() as usize);
+                    return self.write_narrow(space, addr, &bytes).await;
+                }
+            }
+        }
+
+        self.write_value(command).await
+    }
+
+    // Writes a value as it is, whatever its alignment
+    async fn write_value(&mut self, command: Command) -> Outcome<()> {
+        let Command::Write {
+            ref space, addr, ..
+        } = command
+        else {
+            unreachable!("only writes are written as values");
+        };
+        let space = space.clone();
+        self.command_tx.send(command).await?;
+
+        loop {
+            match self.response().await? {
+                Event::Write {
+                    space: ref written_space,
+                    addr: written_addr,
+                    ..
+                } if self.is_space(written_space, &space) && written_addr == addr => return Ok(()),
+                event => warn!(?event, "Ignoring unexpected response"),
+            }
+        }
+    }
+
+    /// Writes `data` starting at `addr` using the most efficient syntax the dialect offers,
+    /// reporting progress after each command.
+    pub(crate) async fn write_block(
+        &mut self,
+        space: &AddressSpace,
+        addr: u64,
+        data: &[u8],
+        progress: &mut Progress,
+    ) -> Outcome<()> {
+        let (mut addr, mut data) = (addr, data);
+        if !addr.is_multiple_of(4) && !data.is_empty() {
+            match self.alignment {
+                Alignment::Unchecked => {}
+                Alignment::Reject => {
+                    let command = Command::WriteBlock {
+                        space: space.clone(),
+                        addr,
+                        data: data.to_vec(),
+                    };
+                    return Err(unaligned(command, addr, Width::Word));
+                }
+                // Bring the rest of the block onto a word boundary
+                Alignment::Split => {
+                    let head = ((4 - addr % 4) as usize).min(data.len());
+                    self.write_narrow(space, addr, &data[..head]).await?;
+                    self.advance(progress, head as u32).await?;
+                    addr += head as u64;
+                    data = &data[head..];
+                }
+            }
+        }
+
+        let (words, tail) = data.split_at(data.len() / 4 * 4);
+        let mut offset = 0;
+
+        match self.dialect.block_write {
+            BlockWrite::Single => {
+                for word in words.chunks(4) {
+                    let word = self.endianness.word_from_bytes(word).into();
+                    self.write(space, addr + offset, word, Width::Word).await?;
+                    offset += 4;
+                    self.advance(progress, 4).await?;
+                }
+            }
+            BlockWrite::MultiValue { max_values } => {
+                for chunk in words.chunks(max_values.max(1) * 4) {
+                    self.write_words(space, addr + offset, chunk).await?;
+                    offset += chunk.len() as u64;
+                    self.advance(progress, chunk.len() as u32).await?;
+                }
+            }
+        }
+
+        if !tail.is_empty() {
+            self.write_partial_word(space, addr + offset, tail).await?;
+            self.advance(progress, tail.len() as u32).await?;
+        }
+
+        Ok(())
+    }
+
+    // Copies through the host a chunk at a time, starting from the end when the destination
+    // overlaps the end of the source
+    async fn copy(&mut self, space: &AddressSpace, src: u64, dst: u64, len: u32) -> Outcome<()> {
+        let mut offsets = (0..len)
+            .step_by(COPY_CHUNK_SIZE as usize)
+            .collect::<Vec<_>>();
+        if dst > src && u128::from(dst) < u128::from(src) + u128::from(len) {
+            offsets.reverse();
+        }
+
+        let mut progress = Progress::new(len);
+        for offset in offsets {
+            let nbytes = (len - offset).min(COPY_CHUNK_SIZE);
+            let data = self.read(space, src + u64::from(offset), nbytes).await?;
+            self.write_block(space, dst + u64::from(offset), &data, &mut progress)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Sends a command the target carries out by itself and waits for its completion event
+    async fn native(&mut self, command: Command, completion: &Event) -> Outcome<()> {
+        self.command_tx.send(command).await?;
+
+        loop {
+            let response = self.response().await?;
+            if response == *completion {
+                return Ok(());
+            }
+            warn!(event = ?response, "Ignoring unexpected response");
+        }
+    }
+
+    // Writes bytes that do not fill an aligned word, using naturally aligned byte and halfword
+    // writes where the dialect has them and otherwise merging them into the aligned words read
+    // back from the target
+    async fn write_narrow(&mut self, space: &AddressSpace, addr: u64, data: &[u8]) -> Outcome<()> {
+        let mut offset = 0;
+        while offset < data.len() {
+            let piece_addr = addr + offset as u64;
+            let remaining = data.len() - offset;
+            let width = if piece_addr.is_multiple_of(2) && remaining >= 2 {
+                Width::Halfword
+            } else {
+                Width::Byte
+            };
+            let len = width.bytes();
+            let command = Command::Fill {
+                space: space.clone(),
+                addr: piece_addr,
+                len,
+                pattern: self
+                    .endianness
+                    .value_from_bytes(&data[offset..offset + len as usize]),
+                width,
+            };
+            if self.dialect.is_native(&command) {
+                let completion = Event::Fill {
+                    addr: piece_addr,
+                    len,
+                };
+                self.native(command, &completion).await?;
+                offset += len as usize;
+            } else {
+                let len = ((4 - piece_addr % 4) as usize).min(remaining);
+                self.write_partial_word(space, piece_addr, &data[offset..offset + len])
+                    .await?;
+                offset += len;
+            }
+        }
+        Ok(())
+    }
+
+    // Reads the range a chunk at a time, keeping the tail of the previous chunk so that matches
+    // spanning two chunks are found
+    async fn search(&mut self, command: &Command) -> Outcome<u32> {
+        let &Command::Search {
+            ref space,
+            addr,
+            len,
+            ref pattern,
+            ref mask,
+            alignment,
+            first_only,
+        } = command
+        else {
+            unreachable!("only searches are searched for");
+        };
+        let alignment = u64::from(alignment.max(1));
+        let pattern_len = pattern.len() as u64;
+        let mut matches = 0;
+        if pattern.is_empty() || pattern_len > u64::from(len) {
+            return Ok(matches);
+        }
+
+        let is_match = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .zip(pattern)
+                .enumerate()
+                .all(|(idx, (byte, expected))| {
+                    let mask = mask.get(idx).copied().unwrap_or(0xff);
+                    byte & mask == expected & mask
+                })
+        };
+
+        let mut progress = Progress::new(len);
+        let mut window = Vec::new();
+        let mut window_addr = addr;
+        // No candidate can start past the top of the address space
+        let Some(mut candidate) = addr.checked_next_multiple_of(alignment) else {
+            return Ok(matches);
+        };
+        for offset in (0..len).step_by(COPY_CHUNK_SIZE as usize) {
+            let nbytes = (len - offset).min(COPY_CHUNK_SIZE);
+            window.extend(self.read(space, addr + u64::from(offset), nbytes).await?);
+            self.advance(&mut progress, nbytes).await?;
+
+            let window_end = window_addr.saturating_add(window.len() as u64);
+            loop {
+                let Some(candidate_end) = candidate.checked_add(pattern_len) else {
+                    return Ok(matches);
+                };
+                if candidate_end > window_end {
+                    break;
+                }
+                let start = (candidate - window_addr) as usize;
+                if is_match(&window[start..start + pattern.len()]) {
+                    matches += 1;
+                    self.send_event(Event::Found { addr: candidate }).await?;
+                    if first_only {
+                        return Ok(matches);
+                    }
+                }
+                let Some(next) = candidate.checked_add(alignment) else {
+                    return Ok(matches);
+                };
+                candidate = next;
+            }
+
+            let consumed = (candidate - window_addr).min(window.len() as u64);
+            window.drain(..consumed as usize);
+            window_addr += consumed;
+        }
+
+        Ok(matches)
+    }
+
+    // Reads the range back through the host a chunk at a time
+    async fn crc32(&mut self, space: &AddressSpace, addr: u64, len: u32) -> Outcome<u32> {
+        let mut digest = CRC32.digest();
+        let mut progress = Progress::new(len);
+        for offset in (0..len).step_by(COPY_CHUNK_SIZE as usize) {
+            let nbytes = (len - offset).min(COPY_CHUNK_SIZE);
+            digest.update(&self.read(space, addr + u64::from(offset), nbytes).await?);
+            self.advance(&mut progress, nbytes).await?;
+        }
+        Ok(digest.finalize())
+    }
+
+    async fn native_crc32(&mut self, command: Command) -> Outcome<u32> {
+        let Command::Crc32 { addr, len, .. } = command else {
+            unreachable!("only CRC-32 commands are computed natively");
+        };
+        self.command_tx.send(command).await?;
+
+        loop {
+            match self.response().await? {
+                Event::Crc32 {
+                    addr: crc_addr,
+                    len: crc_len,
+                    crc,
+                } if (crc_addr, crc_len) == (addr, len) => return Ok(crc),
+                event @ Event::ReadError { .. } => return Err(Failure::Event(event)),
+                event => warn!(?event, "Ignoring unexpected response"),
+            }
+        }
+    }
+
+    fn record_write(&mut self, entry: Entry) -> Result<()> {
+        match &mut self.journal {
+            Some(journal) => journal.record(entry),
+            None => Ok(()),
+        }
+    }
+
+    async fn advance(&self, progress: &mut Progress, nbytes: u32) -> Result<()> {
+        progress.done += nbytes;
+        let (done, total) = (progress.done, progress.total);
+        self.send_event(Event::Progress { done, total }).await
+    }
+
+    async fn write_words(&mut self, space: &AddressSpace, addr: u64, data: &[u8]) -> Outcome<()> {
+        let command = Command::WriteBlock {
+            space: space.clone(),
+            addr,
+            data: data.to_vec(),
+        };
+        self.command_tx.send(command).await?;
+
+        loop {
+            match self.response().await? {
+                // A single word echoes as a plain write
+                Event::WriteBlock {
+                    space: ref written_space,
+                    addr: written_addr,
+                    ..
+                }
+                | Event::Write {
+                    space: ref written_space,
+                    addr: written_addr,
+                    ..
+                } if self.is_space(written_space, space) && written_addr == addr => return Ok(()),
+                event => warn!(?event, "Ignoring unexpected response"),
+            }
+        }
+    }
+
+    // Writes bytes within a single word by merging them into the word read back from the target
+    async fn write_partial_word(
+        &mut self,
+        space: &AddressSpace,
+        addr: u64,
+        data: &[u8],
+    ) -> Outcome<()> {
+        let offset = (addr % 4) as usize;
+        let word_addr = addr - offset as u64;
+        let mut word = self.read_with(space, word_addr, 4, false).await?;
+        word[offset..offset + data.len()].copy_from_slice(data);
+        let command = Command::Write {
+            space: space.clone(),
+            addr: word_addr,
+            data: self.endianness.word_from_bytes(&word).into(),
+            width: Width::Word,
+        };
+        self.write_value(command).await
+    }
+
+    // Shells without address spaces echo every access as one to the kernel
+    fn is_space(&self, echoed: &AddressSpace, space: &AddressSpace) -> bool {
+        !self.dialect.has_address_spaces() || echoed == space
+    }
+
+    // A command refused by the access policy, or held until the prompt timed out, ends the
+    // operation waiting for its response
+    async fn response(&mut self) -> Outcome<Event> {
+        let event = self
+            .response_rx
+            .recv()
+            .await
+            .ok_or("response channel closed")?;
+        match event {
+            Event::AccessDenied { .. } | Event::PromptTimeout { .. } => Err(Failure::Event(event)),
+            event => Ok(event),
+        }
+    }
+
+    pub(crate) async fn send_event(&self, event: Event) -> Result<()> {
+        info!(?event, "Sending event");
+        self.event_tx.send(event).await?;
+        Ok(())
+    }
+}
+
+// Refuses an access that does not start on a boundary of its width
+fn unaligned(command: Command, addr: u64, width: Width) -> Failure {
+    let violation = Violation::Unaligned { addr, width };
+    Failure::Event(Event::AccessDenied { command, violation })
+}
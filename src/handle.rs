@@ -0,0 +1,200 @@
+//! Typed access to target memory.
+//!
+//! A [`DapHandle`] reads and writes [`Value`]s such as `i16`, `f32`, or `[u32; 4]` through the
+//! command and event channels of a running [`crate::UartDap`]. Reads request exactly the bytes of
+//! the value, and writes are broken into writes of the value's natural width so that narrow
+//! registers and access policies see the width they expect.
+
+use tokio::sync::mpsc;
+
+use crate::remote;
+use crate::{AddressSpace, Alignment, Command, Dialect, Endianness, Event, Result, Width};
+
+/// A value with a fixed layout in target memory.
+///
+/// Implemented for the primitive integers and floats, and for arrays of values. Implement it for
+/// a struct to read and write the struct as a whole.
+pub trait Value: Sized {
+    /// Bytes the value occupies.
+    const SIZE: u32;
+    /// The width of the writes the value is broken into. [`Value::SIZE`] must be a multiple of it.
+    const WIDTH: Width;
+
+    /// Forms the value from its [`Value::SIZE`] bytes in memory order.
+    fn from_bytes(bytes: &[u8], endianness: Endianness) -> Self;
+
+    /// Returns the [`Value::SIZE`] bytes of the value in memory order.
+    fn to_bytes(&self, endianness: Endianness) -> Vec<u8>;
+}
+
+macro_rules! impl_value {
+    ($($ty:ty => $width:ident),* $(,)?) => {$(
+        impl Value for $ty {
+            const SIZE: u32 = std::mem::size_of::<$ty>() as u32;
+            const WIDTH: Width = Width::$width;
+
+            fn from_bytes(bytes: &[u8], endianness: Endianness) -> Self {
+                let bytes = bytes.try_into().expect("bytes should match the value size");
+                match endianness {
+                    Endianness::Big => Self::from_be_bytes(bytes),
+                    Endianness::Little => Self::from_le_bytes(bytes),
+                }
+            }
+
+            fn to_bytes(&self, endianness: Endianness) -> Vec<u8> {
+                match endianness {
+                    Endianness::Big => self.to_be_bytes().to_vec(),
+                    Endianness::Little => self.to_le_bytes().to_vec(),
+                }
+            }
+        }
+    )*};
+}
+
+impl_value! {
+    u8 => Byte,
+    i8 => Byte,
+    u16 => Halfword,
+    i16 => Halfword,
+    u32 => Word,
+    i32 => Word,
+    f32 => Word,
+    u64 => Doubleword,
+    i64 => Doubleword,
+    f64 => Doubleword,
+}
+
+impl<T: Value, const N: usize> Value for [T; N] {
+    const SIZE: u32 = T::SIZE * N as u32;
+    const WIDTH: Width = T::WIDTH;
+
+    fn from_bytes(bytes: &[u8], endianness: Endianness) -> Self {
+        let size = T::SIZE as usize;
+        std::array::from_fn(|idx| T::from_bytes(&bytes[idx * size..][..size], endianness))
+    }
+
+    fn to_bytes(&self, endianness: Endianness) -> Vec<u8> {
+        self.iter()
+            .flat_map(|element| element.to_bytes(endianness))
+            .collect()
+    }
+}
+
+/// Reads and writes [`Value`]s in the byte order of the DAP. Created with
+/// [`crate::UartDap::handle`].
+///
+/// Helpers that read memory a range at a time, such as [`crate::Dump`], take a handle rather
+/// than the bare channels so that they form words in the same byte order as the DAP.
+///
+/// On dialects without narrow writes, such as [`Dialect::integrity`] and [`Dialect::vxworks`],
+/// writing a `u8`, `u16`, or an array of them fails unless the DAP was configured with
+/// [`Alignment::Split`], which merges each element into the word around it. That reads the word
+/// and writes it back whole, so it must not be used on registers with side effects.
+pub struct DapHandle<'a> {
+    pub(crate) command_tx: &'a mpsc::Sender<Command>,
+    pub(crate) event_rx: &'a mut mpsc::Receiver<Event>,
+    pub(crate) endianness: Endianness,
+    dialect: Dialect,
+    alignment: Alignment,
+}
+
+impl<'a> DapHandle<'a> {
+    pub(crate) fn new(
+        command_tx: &'a mpsc::Sender<Command>,
+        event_rx: &'a mut mpsc::Receiver<Event>,
+        endianness: Endianness,
+        dialect: Dialect,
+        alignment: Alignment,
+    ) -> Self {
+        Self {
+            command_tx,
+            event_rx,
+            endianness,
+            dialect,
+            alignment,
+        }
+    }
+
+    /// Reads the value at `addr`.
+    pub async fn read<T: Value>(&mut self, addr: u64) -> Result<T> {
+        let bytes = self.read_bytes(addr, T::SIZE).await?;
+        Ok(T::from_bytes(&bytes, self.endianness))
+    }
+
+    /// Reads `n` consecutive values starting at `addr` with a single read.
+    pub async fn read_array<T: Value>(&mut self, addr: u64, n: usize) -> Result<Vec<T>> {
+        let nbytes = u32::try_from(n)
+            .ok()
+            .and_then(|n| n.checked_mul(T::SIZE))
+            .ok_or_else(|| format!("{n} values do not fit in a single read"))?;
+        let bytes = self.read_bytes(addr, nbytes).await?;
+        let size = T::SIZE as usize;
+        Ok((0..n)
+            .map(|idx| T::from_bytes(&bytes[idx * size..][..size], self.endianness))
+            .collect())
+    }
+
+    /// Writes `value` at `addr` one element of [`Value::WIDTH`] at a time.
+    ///
+    /// Elements are written with a [`Command::Fill`] of their width where the dialect fills
+    /// natively, as U-Boot `mw.b`, `mw.w`, `mw.l`, and `mw.q` do, and words and doublewords are
+    /// otherwise written a word at a time with [`Command::Write`]. Bytes and halfwords can then
+    /// only be written by reading and writing back the whole word around them, which is not safe
+    /// on registers. They are sent as narrow [`Command::Write`]s for the DAP to merge under
+    /// [`Alignment::Split`], and are otherwise refused before anything is sent.
+    pub async fn write<T: Value>(&mut self, addr: u64, value: T) -> Result<()> {
+        let width = T::WIDTH;
+        let len = width.bytes();
+        let is_narrow = len < Width::Word.bytes();
+        let bytes = value.to_bytes(self.endianness);
+        let elements = (addr..)
+            .step_by(len as usize)
+            .zip(bytes.chunks(len as usize));
+        // Whether the element at `addr` can be filled natively, whatever its value
+        let is_native = |addr| {
+            self.dialect.is_native(&Command::Fill {
+                space: AddressSpace::kernel(),
+                addr,
+                len,
+                pattern: 0,
+                width,
+            })
+        };
+
+        if is_narrow && self.alignment != Alignment::Split {
+            if let Some((addr, _)) = elements.clone().find(|&(addr, _)| !is_native(addr)) {
+                return Err(format!("the dialect cannot write {len} bytes at {addr:#x}").into());
+            }
+        }
+
+        for (addr, element) in elements {
+            if is_native(addr) {
+                let pattern = self.endianness.value_from_bytes(element);
+                remote::fill(self.command_tx, self.event_rx, addr, len, pattern, width).await?;
+                continue;
+            }
+            if is_narrow {
+                let data = self.endianness.value_from_bytes(element);
+                remote::write(self.command_tx, self.event_rx, addr, data, width).await?;
+                continue;
+            }
+            for (addr, word) in (addr..).step_by(4).zip(element.chunks(4)) {
+                let data = self.endianness.word_from_bytes(word);
+                let (data, width) = (u64::from(data), Width::Word);
+                remote::write(self.command_tx, self.event_rx, addr, data, width).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn read_bytes(&mut self, addr: u64, nbytes: u32) -> Result<Vec<u8>> {
+        remote::read_bytes(
+            self.command_tx,
+            self.event_rx,
+            addr,
+            nbytes,
+            self.endianness,
+        )
+        .await
+    }
+}
@@ -0,0 +1,235 @@
+//! Parser for the hexdump style responses printed by target memory read commands.
+//!
+//! Handles the layouts commonly found on target shells:
+//!
+//! ```text
+//! c0000010: 03 0a 30 18  00 00 00 00  00 00 00 80  00 07 00 00 |..0.............|
+//! 0x00100000:  1234 5678 9abc def0  *.4Vx....*
+//! 80000000: 12345678 9abcdef0 00000000 00000000    .4Vx............
+//! 80000000: 12345678 9abcdef0
+//! ```
+//!
+//! Any number of groups per line is accepted. Groups of bytes, halfwords, words, or doublewords
+//! are converted to bytes in memory order using the configured [`Endianness`]. The ASCII column
+//! may be delimited by `|` or `*`, separated by whitespace, or omitted entirely.
+//!
+//! Because the response to a read is just console output, a line that happens to look like a
+//! hexdump is only trusted by [`HexdumpParser::parse_expected`] if its address, length, and ASCII
+//! column are consistent with the read in flight.
+
+use std::fmt;
+
+use crate::Endianness;
+
+/// The most data bytes a single response line is expected to hold.
+pub const MAX_BYTES_PER_LINE: usize = 64;
+
+/// A successfully parsed hexdump line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexdumpLine {
+    /// The address printed at the start of the line.
+    pub addr: u64,
+    /// The data bytes in memory order.
+    pub bytes: Vec<u8>,
+    /// The ASCII column, if present.
+    pub ascii: Option<String>,
+}
+
+impl HexdumpLine {
+    /// Returns true if the ASCII column is absent or agrees with the data bytes.
+    ///
+    /// Printable bytes must appear as themselves and non-printable bytes as punctuation such as
+    /// `.`. Whitespace is ignored since it cannot be told apart from column padding.
+    pub fn ascii_matches(&self) -> bool {
+        let Some(ascii) = &self.ascii else {
+            return true;
+        };
+
+        let mut column = ascii.chars().filter(|c| !c.is_whitespace());
+        self.bytes
+            .iter()
+            .filter(|&&byte| byte != b' ')
+            .all(|&byte| match column.next() {
+                Some(c) if byte.is_ascii_graphic() => c == byte as char,
+                Some(c) => !c.is_alphanumeric(),
+                None => false,
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexdumpError {
+    /// The line does not have the `<addr>: <groups>` layout of a hexdump line.
+    NotHexdump,
+    /// The line address does not match the address of the data being waited on.
+    AddressMismatch { expected: u64, actual: u64 },
+    /// The line holds no data or more data than any target prints on a single line.
+    ImplausibleLength { len: usize },
+    /// The ASCII column does not agree with the data bytes.
+    AsciiMismatch,
+}
+
+impl fmt::Display for HexdumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotHexdump => write!(f, "not a hexdump line"),
+            Self::AddressMismatch { expected, actual } => write!(
+                f,
+                "hexdump line address {actual:#x} does not match expected address {expected:#x}"
+            ),
+            Self::ImplausibleLength { len } => {
+                write!(f, "hexdump line has an implausible length of {len} bytes")
+            }
+            Self::AsciiMismatch => write!(f, "hexdump line ASCII column does not match its data"),
+        }
+    }
+}
+
+impl std::error::Error for HexdumpError {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HexdumpParser {
+    endianness: Endianness,
+}
+
+impl HexdumpParser {
+    pub fn new(endianness: Endianness) -> Self {
+        Self { endianness }
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Parses a line without any expectation of its address or length.
+    pub fn parse_line(&self, line: &str) -> Result<HexdumpLine, HexdumpError> {
+        self.parse(line, usize::MAX)
+    }
+
+    /// Parses a line belonging to a read that expects `remaining` more bytes starting at
+    /// `expected_addr`.
+    ///
+    /// At most `remaining` bytes are consumed from the line, which keeps a short final line from
+    /// mistaking an undelimited ASCII column for data. The number of bytes consumed is the length
+    /// of [`HexdumpLine::bytes`].
+    ///
+    /// The line is rejected if its address is not `expected_addr`, if its length is implausible,
+    /// or if its ASCII column (when present) disagrees with the data.
+    pub fn parse_expected(
+        &self,
+        line: &str,
+        expected_addr: u64,
+        remaining: usize,
+    ) -> Result<HexdumpLine, HexdumpError> {
+        let parsed = self.parse(line, remaining)?;
+        if parsed.addr != expected_addr {
+            return Err(HexdumpError::AddressMismatch {
+                expected: expected_addr,
+                actual: parsed.addr,
+            });
+        }
+        if parsed.bytes.is_empty() || parsed.bytes.len() > MAX_BYTES_PER_LINE {
+            return Err(HexdumpError::ImplausibleLength {
+                len: parsed.bytes.len(),
+            });
+        }
+        if !parsed.ascii_matches() {
+            return Err(HexdumpError::AsciiMismatch);
+        }
+        Ok(parsed)
+    }
+
+    fn parse(&self, line: &str, limit: usize) -> Result<HexdumpLine, HexdumpError> {
+        let (prefix, remaining) = line
+            .match_indices(':')
+            .map(|(idx, _)| line.split_at(idx))
+            .map(|(prefix, remaining)| (prefix, &remaining[1..]))
+            .find(|(_, remaining)| remaining.starts_with(char::is_whitespace))
+            .ok_or(HexdumpError::NotHexdump)?;
+
+        // The address may be preceded by other text such as a timestamp
+        let addr_token = prefix
+            .split_ascii_whitespace()
+            .last()
+            .ok_or(HexdumpError::NotHexdump)?;
+        let addr_token = addr_token
+            .strip_prefix("0x")
+            .or_else(|| addr_token.strip_prefix("0X"))
+            .unwrap_or(addr_token);
+        let addr = u64::from_str_radix(addr_token, 16).map_err(|_| HexdumpError::NotHexdump)?;
+
+        let (groups, mut ascii) = match remaining.find(['|', '*']) {
+            Some(start) => {
+                let delimiter = remaining[start..].chars().next().unwrap();
+                let column = &remaining[start + 1..];
+                let column = column.rfind(delimiter).map_or(column, |end| &column[..end]);
+                (&remaining[..start], Some(column.to_string()))
+            }
+            None => (remaining, None),
+        };
+
+        let mut tokens = groups.split_ascii_whitespace().peekable();
+        let width = tokens
+            .peek()
+            .map(|token| token.len())
+            .filter(|width| matches!(width, 2 | 4 | 8 | 16))
+            .ok_or(HexdumpError::NotHexdump)?;
+
+        let mut bytes = Vec::new();
+        while let Some(&token) = tokens.peek() {
+            if bytes.len() >= limit {
+                break;
+            }
+            let Some(group) = self.group_bytes(token, width) else {
+                break;
+            };
+            bytes.extend(group);
+            tokens.next();
+        }
+
+        // Whatever follows the data groups is an undelimited ASCII column. Targets that always
+        // print whole lines leave data groups beyond `limit` in front of it, which are told apart
+        // from the column by the column having a character for each byte on the line.
+        if ascii.is_none() {
+            let rest = tokens.collect::<Vec<_>>();
+            let mut line_bytes = bytes.clone();
+            let mut start = 0;
+            while start < rest.len() && !column_fits(&rest[start..], &line_bytes) {
+                let Some(group) = self.group_bytes(rest[start], width) else {
+                    break;
+                };
+                line_bytes.extend(group);
+                start += 1;
+            }
+            let column = rest[start..].join(" ");
+            if !column.is_empty() {
+                ascii = Some(column);
+            }
+        }
+        bytes.truncate(limit);
+
+        Ok(HexdumpLine { addr, bytes, ascii })
+    }
+
+    // Converts a group of `width` hex digits to bytes in memory order
+    fn group_bytes(&self, token: &str, width: usize) -> Option<Vec<u8>> {
+        if token.len() != width {
+            return None;
+        }
+        let group = u64::from_str_radix(token, 16).ok()?;
+        let group = &group.to_be_bytes()[8 - width / 2..];
+        Some(match self.endianness {
+            Endianness::Big => group.to_vec(),
+            Endianness::Little => group.iter().rev().copied().collect(),
+        })
+    }
+}
+
+// Returns true if `tokens` have as many characters as `bytes` has bytes, not counting whitespace
+fn column_fits(tokens: &[&str], bytes: &[u8]) -> bool {
+    let chars = tokens
+        .iter()
+        .map(|token| token.chars().count())
+        .sum::<usize>();
+    chars == bytes.iter().filter(|&&byte| byte != b' ').count()
+}
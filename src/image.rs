@@ -0,0 +1,411 @@
+//! Memory image file formats.
+//!
+//! An image is a list of [`Segment`]s, each a run of bytes at an address. Images are written as
+//! raw binary, Intel HEX, Motorola S-record, or a canonical hexdump as printed by `hexdump -C`, and
+//! read from raw binary, Intel HEX, Motorola S-record, or the `PT_LOAD` segments of an ELF file.
+
+use std::fmt;
+use std::io::Write;
+
+use crate::Result;
+
+/// Data bytes per Intel HEX or S-record record.
+const BYTES_PER_RECORD: usize = 16;
+/// Data bytes per canonical hexdump line.
+const BYTES_PER_LINE: usize = 16;
+/// Fills the gaps between segments in raw binary images.
+const GAP_FILL: u8 = 0xff;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const PT_LOAD: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Raw bytes starting at the lowest address. Gaps between segments are filled with `0xff`.
+    Binary,
+    IntelHex,
+    SRecord,
+    /// `hexdump -C` style lines. Write only.
+    Hexdump,
+    /// The `PT_LOAD` segments of an ELF executable. Read only.
+    Elf,
+}
+
+impl ImageFormat {
+    /// Guesses the format of the image file contents `data`. Anything that is not recognized is
+    /// assumed to be raw binary.
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(ELF_MAGIC) {
+            Self::Elf
+        } else if data.starts_with(b":") {
+            Self::IntelHex
+        } else if data.starts_with(b"S0") || data.starts_with(b"S1") || data.starts_with(b"S3") {
+            Self::SRecord
+        } else {
+            Self::Binary
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Binary => write!(f, "binary"),
+            Self::IntelHex => write!(f, "Intel HEX"),
+            Self::SRecord => write!(f, "S-record"),
+            Self::Hexdump => write!(f, "hexdump"),
+            Self::Elf => write!(f, "ELF"),
+        }
+    }
+}
+
+/// Consecutive bytes starting at `addr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub addr: u64,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    pub fn new(addr: u64, data: Vec<u8>) -> Self {
+        Self { addr, data }
+    }
+
+    /// The address following the last byte, which is past the 64-bit address space for a segment
+    /// that ends at its top.
+    pub fn end(&self) -> u128 {
+        u128::from(self.addr) + self.data.len() as u128
+    }
+}
+
+/// Writes `segments` to `writer` in `format`.
+///
+/// Intel HEX and S-record images have 32-bit addresses and fail at the first record that would
+/// start at or above 4 GiB.
+pub fn write_image(
+    writer: &mut impl Write,
+    format: ImageFormat,
+    segments: &[Segment],
+) -> Result<()> {
+    match format {
+        ImageFormat::Binary => write_binary(writer, segments),
+        ImageFormat::IntelHex => write_intel_hex(writer, segments),
+        ImageFormat::SRecord => write_srecord(writer, segments),
+        ImageFormat::Hexdump => write_hexdump(writer, segments),
+        ImageFormat::Elf => Err("writing ELF images is not supported".into()),
+    }
+}
+
+/// Reads the segments of the image file contents `data` in `format`. `base` is the address of the
+/// first byte of a raw binary image and is ignored by other formats.
+///
+/// Adjacent records are merged into a single segment.
+pub fn read_image(data: &[u8], format: ImageFormat, base: u64) -> Result<Vec<Segment>> {
+    match format {
+        ImageFormat::Binary => Ok(vec![Segment::new(base, data.to_vec())]),
+        ImageFormat::IntelHex => read_intel_hex(std::str::from_utf8(data)?),
+        ImageFormat::SRecord => read_srecord(std::str::from_utf8(data)?),
+        ImageFormat::Elf => read_elf(data),
+        ImageFormat::Hexdump => Err("reading hexdump images is not supported".into()),
+    }
+}
+
+// Appends to the last segment if `data` immediately follows it
+fn push_data(segments: &mut Vec<Segment>, addr: u64, data: &[u8]) {
+    match segments.last_mut() {
+        Some(last) if last.end() == u128::from(addr) => last.data.extend(data),
+        _ => segments.push(Segment::new(addr, data.to_vec())),
+    }
+}
+
+// Decodes the hex digits of a record and checks that its length is the count in its first byte
+// plus `overhead`
+fn decode_record(line_no: usize, text: &str, overhead: usize) -> Result<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return Err(format!("line {line_no}: malformed record").into());
+    }
+    let bytes = (0..text.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(&text[idx..idx + 2], 16))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|_| format!("line {line_no}: malformed record"))?;
+    if bytes.len() < 2 || bytes[0] as usize + overhead != bytes.len() {
+        return Err(format!("line {line_no}: record length does not match its contents").into());
+    }
+    Ok(bytes)
+}
+
+fn read_intel_hex(text: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut base = 0u32;
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let record = line
+            .strip_prefix(':')
+            .ok_or_else(|| format!("line {line_no}: missing ':'"))?;
+        // Length, address, type, and checksum surround the data
+        let record = decode_record(line_no, record, 5)?;
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(format!("line {line_no}: bad checksum").into());
+        }
+
+        let addr = u16::from_be_bytes([record[1], record[2]]);
+        let data = &record[4..record.len() - 1];
+        match record[3] {
+            0x00 => {
+                let addr = base.wrapping_add(addr.into());
+                push_data(&mut segments, addr.into(), data)
+            }
+            0x01 => break,
+            0x02 if data.len() == 2 => {
+                base = u32::from(u16::from_be_bytes([data[0], data[1]])) << 4
+            }
+            0x04 if data.len() == 2 => {
+                base = u32::from(u16::from_be_bytes([data[0], data[1]])) << 16
+            }
+            // Start addresses
+            0x03 | 0x05 => {}
+            kind => {
+                return Err(format!("line {line_no}: unsupported record type {kind:02x}").into())
+            }
+        }
+    }
+
+    Ok(segments)
+}
+
+fn read_srecord(text: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (kind, record) = line
+            .strip_prefix('S')
+            .filter(|rest| rest.len() >= 2 && rest.is_char_boundary(1))
+            .map(|rest| rest.split_at(1))
+            .ok_or_else(|| format!("line {line_no}: missing 'S'"))?;
+        // The count covers everything after it
+        let record = decode_record(line_no, record, 1)?;
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0xff {
+            return Err(format!("line {line_no}: bad checksum").into());
+        }
+
+        let addr_len = match kind {
+            "1" => 2,
+            "2" => 3,
+            "3" => 4,
+            // Header, count, and termination records
+            "0" | "5" | "6" | "7" | "8" | "9" => continue,
+            kind => return Err(format!("line {line_no}: unsupported record type S{kind}").into()),
+        };
+        let body = &record[1..record.len() - 1];
+        if body.len() < addr_len {
+            return Err(format!("line {line_no}: record too short").into());
+        }
+        let (addr, data) = body.split_at(addr_len);
+        let addr = addr
+            .iter()
+            .fold(0u32, |addr, &byte| (addr << 8) | u32::from(byte));
+        push_data(&mut segments, addr.into(), data);
+    }
+
+    Ok(segments)
+}
+
+// Loads each `PT_LOAD` segment at its physical address. Memory beyond the file contents of a
+// segment, such as `.bss`, is zero filled.
+fn read_elf(data: &[u8]) -> Result<Vec<Segment>> {
+    let field = |offset: usize, len: usize| -> Result<u64> {
+        let bytes = data
+            .get(offset..offset.saturating_add(len))
+            .ok_or("ELF file is truncated")?;
+        let fold = |value: u64, &byte: &u8| (value << 8) | u64::from(byte);
+        Ok(match data.get(5) {
+            Some(2) => bytes.iter().fold(0, fold),
+            _ => bytes.iter().rev().fold(0, fold),
+        })
+    };
+
+    if !data.starts_with(ELF_MAGIC) {
+        return Err("not an ELF file".into());
+    }
+    let is_64 = match data.get(4) {
+        Some(1) => false,
+        Some(2) => true,
+        _ => return Err("unknown ELF class".into()),
+    };
+
+    // Offsets of the program header table fields and of the program header fields
+    let (phoff, phentsize, phnum) = if is_64 {
+        (field(0x20, 8)?, field(0x36, 2)?, field(0x38, 2)?)
+    } else {
+        (field(0x1c, 4)?, field(0x2a, 2)?, field(0x2c, 2)?)
+    };
+    let (offset, paddr, filesz, memsz, width) = if is_64 {
+        (0x08, 0x18, 0x20, 0x28, 8)
+    } else {
+        (0x04, 0x0c, 0x10, 0x14, 4)
+    };
+
+    let mut segments = Vec::new();
+    for idx in 0..phnum {
+        let header = phoff.saturating_add(idx * phentsize) as usize;
+        if field(header, 4)? != u64::from(PT_LOAD) {
+            continue;
+        }
+        let file_offset = field(header.saturating_add(offset), width)? as usize;
+        let addr = field(header.saturating_add(paddr), width)?;
+        let filesz = field(header.saturating_add(filesz), width)? as usize;
+        let memsz = field(header.saturating_add(memsz), width)? as usize;
+        if memsz == 0 {
+            continue;
+        }
+
+        if u128::from(addr) + memsz.max(filesz) as u128 > 1 << 64 {
+            return Err(format!("segment at {addr:#x} does not fit the address space").into());
+        }
+        let mut contents = data
+            .get(file_offset..file_offset.saturating_add(filesz))
+            .ok_or("ELF file is truncated")?
+            .to_vec();
+        contents.resize(memsz.max(filesz), 0);
+        segments.push(Segment::new(addr, contents));
+    }
+
+    Ok(segments)
+}
+
+fn write_binary(writer: &mut impl Write, segments: &[Segment]) -> Result<()> {
+    let mut segments = segments.iter().collect::<Vec<_>>();
+    segments.sort_by_key(|segment| segment.addr);
+
+    let mut next = None;
+    for segment in segments {
+        let gap = next.map_or(0, |next| u128::from(segment.addr).saturating_sub(next));
+        writer.write_all(&vec![GAP_FILL; gap as usize])?;
+        writer.write_all(&segment.data)?;
+        next = Some(segment.end());
+    }
+    Ok(())
+}
+
+fn write_intel_hex(writer: &mut impl Write, segments: &[Segment]) -> Result<()> {
+    let mut upper = 0;
+    for segment in segments {
+        for (idx, chunk) in segment.data.chunks(BYTES_PER_RECORD).enumerate() {
+            let offset = idx * BYTES_PER_RECORD;
+            // Records may not cross a 64 KiB boundary
+            let page_offset = (segment.addr + offset as u64) & 0xffff;
+            let split = (0x10000 - page_offset as usize).min(chunk.len());
+            let (head, tail) = chunk.split_at(split);
+            for (offset, chunk) in [(offset, head), (offset + split, tail)] {
+                if chunk.is_empty() {
+                    continue;
+                }
+                let addr = record_addr(segment, offset, ImageFormat::IntelHex)?;
+                if addr >> 16 != upper {
+                    upper = addr >> 16;
+                    let upper = (upper as u16).to_be_bytes();
+                    write_intel_hex_record(writer, 0, 0x04, &upper)?;
+                }
+                write_intel_hex_record(writer, addr as u16, 0x00, chunk)?;
+            }
+        }
+    }
+    write_intel_hex_record(writer, 0, 0x01, &[])
+}
+
+// The address of the record starting `offset` bytes into `segment`, in a format with 32-bit
+// addresses
+fn record_addr(segment: &Segment, offset: usize, format: ImageFormat) -> Result<u32> {
+    let addr = segment.addr + offset as u64;
+    u32::try_from(addr).map_err(|_| format!("{format} cannot address {addr:#x}").into())
+}
+
+fn write_intel_hex_record(writer: &mut impl Write, addr: u16, kind: u8, data: &[u8]) -> Result<()> {
+    let mut record = vec![data.len() as u8];
+    record.extend(addr.to_be_bytes());
+    record.push(kind);
+    record.extend(data);
+    let checksum = record
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+    record.push(checksum);
+    writeln!(writer, ":{}", hex(&record))?;
+    Ok(())
+}
+
+fn write_srecord(writer: &mut impl Write, segments: &[Segment]) -> Result<()> {
+    write_srecord_record(writer, '0', &[0, 0], b"uart-dap")?;
+    for segment in segments {
+        for (idx, chunk) in segment.data.chunks(BYTES_PER_RECORD).enumerate() {
+            let addr = record_addr(segment, idx * BYTES_PER_RECORD, ImageFormat::SRecord)?;
+            write_srecord_record(writer, '3', &addr.to_be_bytes(), chunk)?;
+        }
+    }
+    write_srecord_record(writer, '7', &0u32.to_be_bytes(), &[])
+}
+
+fn write_srecord_record(
+    writer: &mut impl Write,
+    kind: char,
+    addr: &[u8],
+    data: &[u8],
+) -> Result<()> {
+    let mut record = vec![(addr.len() + data.len() + 1) as u8];
+    record.extend(addr);
+    record.extend(data);
+    let checksum = !record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    record.push(checksum);
+    writeln!(writer, "S{kind}{}", hex(&record))?;
+    Ok(())
+}
+
+fn write_hexdump(writer: &mut impl Write, segments: &[Segment]) -> Result<()> {
+    for segment in segments {
+        for (idx, chunk) in segment.data.chunks(BYTES_PER_LINE).enumerate() {
+            let addr = segment.addr + (idx * BYTES_PER_LINE) as u64;
+            let mut groups = chunk
+                .chunks(8)
+                .map(|group| {
+                    group
+                        .iter()
+                        .map(|byte| format!("{byte:02x}"))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+                .join("  ");
+            // Keep the ASCII column aligned on short lines
+            let width = BYTES_PER_LINE * 3;
+            groups = format!("{groups:width$}");
+            let ascii = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            writeln!(writer, "{addr:08x}  {groups}  |{ascii}|")?;
+        }
+        writeln!(writer, "{:08x}", segment.end())?;
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02X}")).collect()
+}
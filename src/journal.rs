@@ -0,0 +1,79 @@
+//! A record of the values written on behalf of the application so that they can be undone.
+//!
+//! Each write is appended to the journal file as a line like:
+//!
+//! ```text
+//! write 1697640000.123456 kernel 0x00001000 0x00000001 0x00000101
+//! ```
+//!
+//! giving the host time, the address space and address, the value before, and the value written.
+//! Values are padded to the width written, so a doubleword write shows sixteen hex digits. Undoing a write appends an `undo` line in the same form with the value restored last. Only
+//! writes made since the DAP started can be undone.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{AddressSpace, Result, Width};
+
+/// A value written on behalf of the application.
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    pub(crate) space: AddressSpace,
+    pub(crate) addr: u64,
+    pub(crate) width: Width,
+    pub(crate) old: u64,
+    pub(crate) new: u64,
+}
+
+pub(crate) struct Journal {
+    file: File,
+    entries: Vec<Entry>,
+}
+
+impl Journal {
+    /// Appends to the file at `path`, creating it if needed.
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file,
+            entries: Vec::new(),
+        })
+    }
+
+    pub(crate) fn record(&mut self, entry: Entry) -> Result<()> {
+        self.append("write", &entry, entry.old, entry.new)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// The most recent write that has not been undone.
+    pub(crate) fn last(&self) -> Option<&Entry> {
+        self.entries.last()
+    }
+
+    /// Records that the most recent write was undone by writing its old value back, and removes
+    /// it. Called only once the old value is written, so that a write that could not be undone
+    /// stays in the journal.
+    pub(crate) fn record_undo(&mut self) -> Result<()> {
+        if let Some(entry) = self.entries.pop() {
+            self.append("undo", &entry, entry.new, entry.old)?;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, kind: &str, entry: &Entry, old: u64, new: u64) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let timestamp = format!("{}.{:06}", timestamp.as_secs(), timestamp.subsec_micros());
+        let Entry {
+            space, addr, width, ..
+        } = entry;
+        let digits = width.bytes() as usize * 2 + 2;
+        writeln!(
+            self.file,
+            "{kind} {timestamp} {space} {addr:#010x} {old:#0digits$x} {new:#0digits$x}"
+        )?;
+        Ok(())
+    }
+}
@@ -1,23 +1,62 @@
 use std::fmt;
-use std::num::ParseIntError;
+use std::io::Write;
 use std::ops::Deref;
-use std::str::{self, FromStr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
 use bytes::{BufMut, BytesMut};
-use if_chain::if_chain;
 use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, WriteHalf};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio_serial::SerialPortBuilderExt;
 use tokio_serial::SerialStream;
-use tracing::{info, trace};
+use tracing::{info, trace, warn};
+
+pub mod checksum;
+mod console;
+mod dialect;
+mod dry_run;
+pub mod dump;
+mod executor;
+pub mod handle;
+pub mod hexdump;
+pub mod image;
+mod journal;
+pub mod load;
+mod login;
+pub mod policy;
+mod remote;
+pub mod sampler;
+pub mod snapshot;
+pub mod vcd;
+pub mod verify;
+
+pub use checksum::{Algorithm, Checksum, ChecksumValue};
+pub use console::{decode_line, Statistics, StatisticsHandle};
+pub use dialect::{BlockWrite, Dialect, Syntax};
+pub use dump::Dump;
+pub use handle::{DapHandle, Value};
+pub use hexdump::{HexdumpError, HexdumpLine, HexdumpParser};
+pub use image::{ImageFormat, Segment};
+pub use load::{LoadReport, Loader};
+pub use login::{Credentials, PASSWORD_VAR, USERNAME_VAR};
+pub use policy::{Access, AccessPolicy, Alignment, Rule, Violation};
+pub use sampler::{SampleFormat, SampleReport, Sampler};
+pub use snapshot::{Region, Snapshot, WordChange};
+pub use vcd::{Signal, VcdWriter};
+pub use verify::{Mismatch, Verifier, VerifyReport};
+
+use executor::Executor;
+use journal::Journal;
+use login::{LoginState, MAX_LOGIN_ATTEMPTS};
 
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 const LINE_BUFFER_SIZE: usize = 4096;
-const MAX_BYTES_PER_LINE: u32 = 16;
 const READ_DEFAULT_NBYTES: u32 = 16;
+const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Echo {
@@ -34,12 +73,59 @@ pub enum LineEnding {
 impl fmt::Display for LineEnding {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            LineEnding::Lf => write!(f, "\n"),
+            LineEnding::Lf => writeln!(f),
             LineEnding::CrLf => write!(f, "\r\n"),
         }
     }
 }
 
+/// Byte order used to combine bytes read from the target into words.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+
+impl Endianness {
+    /// Combines up to four bytes in memory order into a word.
+    pub fn word_from_bytes(self, bytes: &[u8]) -> u32 {
+        let fold = |word: u32, &byte: &u8| (word << 8) | u32::from(byte);
+        match self {
+            Self::Big => bytes.iter().fold(0, fold),
+            Self::Little => bytes.iter().rev().fold(0, fold),
+        }
+    }
+
+    /// Splits a word into its first `len` bytes in memory order. The inverse of
+    /// [`Endianness::word_from_bytes`].
+    pub fn word_to_bytes(self, word: u32, len: usize) -> Vec<u8> {
+        match self {
+            Self::Big => word.to_be_bytes()[4 - len..].to_vec(),
+            Self::Little => word.to_le_bytes()[..len].to_vec(),
+        }
+    }
+
+    /// Combines up to eight bytes in memory order into a value, such as a [`Command::Fill`]
+    /// pattern of [`Width::Doubleword`].
+    pub fn value_from_bytes(self, bytes: &[u8]) -> u64 {
+        let fold = |value: u64, &byte: &u8| (value << 8) | u64::from(byte);
+        match self {
+            Self::Big => bytes.iter().fold(0, fold),
+            Self::Little => bytes.iter().rev().fold(0, fold),
+        }
+    }
+
+    /// Splits a value into its first `len` bytes in memory order. The inverse of
+    /// [`Endianness::value_from_bytes`].
+    pub fn value_to_bytes(self, value: u64, len: usize) -> Vec<u8> {
+        match self {
+            Self::Big => value.to_be_bytes()[8 - len..].to_vec(),
+            Self::Little => value.to_le_bytes()[..len].to_vec(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Target {
     /// Wind River VxWorks
@@ -48,34 +134,451 @@ pub enum Target {
     Integrity,
 }
 
+/// Size of each element written by [`Command::Fill`], or of the accesses an [`AccessPolicy`] rule
+/// requires.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Width {
+    Byte,
+    Halfword,
+    Word,
+    Doubleword,
+}
+
+impl Width {
+    pub fn bytes(self) -> u32 {
+        match self {
+            Self::Byte => 1,
+            Self::Halfword => 2,
+            Self::Word => 4,
+            Self::Doubleword => 8,
+        }
+    }
+
+    fn from_bytes(bytes: u32) -> Option<Self> {
+        match bytes {
+            1 => Some(Self::Byte),
+            2 => Some(Self::Halfword),
+            4 => Some(Self::Word),
+            8 => Some(Self::Doubleword),
+            _ => None,
+        }
+    }
+
+    /// Keeps the low bytes of `value` that fit in the width.
+    pub(crate) fn truncate(self, value: u64) -> u64 {
+        value & (u64::MAX >> (64 - 8 * self.bytes()))
+    }
+}
+
+/// A named address space of the target, such as `kernel` or the AddressSpace of an Integrity
+/// task. U-Boot has a single address space and ignores it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AddressSpace(String);
+
+impl AddressSpace {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    /// The address space of the kernel, which commands access unless they name another.
+    pub fn kernel() -> Self {
+        Self::new("kernel")
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for AddressSpace {
+    fn default() -> Self {
+        Self::kernel()
+    }
+}
+
+impl fmt::Display for AddressSpace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// An operation on target memory.
+///
+/// Every command other than [`Command::Undo`] names the [`AddressSpace`] it accesses, which is the
+/// kernel's when left out of its serialized form. [`Command::Undo`] restores each word in the
+/// space it was written in. The helpers built on commands, such as [`Dump`], [`Loader`],
+/// [`Verifier`], [`Sampler`], [`Snapshot`], [`Checksum`], and [`DapHandle`], access the kernel
+/// address space.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Command {
-    Read { addr: u32, nbytes: u32 },
-    Write { addr: u32, data: u32 },
+    Read {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        nbytes: u32,
+    },
+    /// Writes the low `width` bytes of `data` at `addr` with a single command, so that a
+    /// doubleword register is written whole. Bytes and halfwords are written only where the dialect
+    /// has narrow writes, and are otherwise reported as [`Event::Unsupported`] unless
+    /// [`Alignment::Split`] allows merging them into the word around them.
+    Write {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        data: u64,
+        width: Width,
+    },
+    /// Writes consecutive bytes starting at `addr` as a single operation, reporting
+    /// [`Event::Progress`] along the way and [`Event::WriteBlock`] once done.
+    WriteBlock {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        data: Vec<u8>,
+    },
+    /// Writes `len` bytes starting at `addr` with repeated copies of the low `width` bytes of
+    /// `pattern`. Reports [`Event::Fill`] once done.
+    Fill {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        len: u32,
+        pattern: u64,
+        width: Width,
+    },
+    /// Copies `len` bytes from `src` to `dst`, both in `space`. The regions may overlap. Reports
+    /// [`Event::Copy`] once done.
+    Copy {
+        #[serde(default)]
+        space: AddressSpace,
+        src: u64,
+        dst: u64,
+        len: u32,
+    },
+    /// Replaces the bits of the word at `addr` selected by `mask` with those of `value`. No other
+    /// command is sent between the read and the write. Reports [`Event::Modify`] once done.
+    Modify {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        mask: u32,
+        value: u32,
+    },
+    /// Reads the word at `addr` every `interval` until the bits selected by `mask` equal
+    /// `expected`. Reports [`Event::WaitFor`] once they do or [`Event::WaitTimeout`] if they still
+    /// do not after `timeout`.
+    WaitFor {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        mask: u32,
+        expected: u32,
+        interval: Duration,
+        timeout: Duration,
+    },
+    /// Reads the `nbytes` starting at `addr` every `period` in between other commands, reporting
+    /// [`Event::Watched`] for each word of the first read and [`Event::Changed`] for each word
+    /// whose value differs from the previous read. Replaces any watch already registered at
+    /// `addr` in `space`.
+    Watch {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        nbytes: u32,
+        period: Duration,
+    },
+    /// Stops the watch registered at `addr` in `space`.
+    Unwatch {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+    },
+    /// Computes the CRC-32 (IEEE 802.3) of `len` bytes starting at `addr`. Reports
+    /// [`Event::Crc32`] once done.
+    Crc32 {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        len: u32,
+    },
+    /// Restores the words replaced by the most recent `count` journaled writes, newest first, or by
+    /// every journaled write if `count` is `None`. Reports [`Event::Undo`] once done. Requires
+    /// [`UartDap::with_journal`].
+    ///
+    /// Only [`Command::Write`] and [`Command::Modify`] are journaled. Words written by
+    /// [`Command::WriteBlock`], [`Command::Fill`], or [`Command::Copy`], and so by loading an
+    /// image or by a [`DapHandle`] on a dialect that fills natively, cannot be undone.
+    Undo { count: Option<u32> },
+    /// Scans `len` bytes starting at `addr` for `pattern` at addresses that are a multiple of
+    /// `alignment`. Only the bits set in `mask` are compared, and an empty mask compares every
+    /// bit. Reports [`Event::Found`] for each match as it is found and [`Event::Search`] once
+    /// done, stopping after the first match if `first_only`.
+    Search {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        len: u32,
+        pattern: Vec<u8>,
+        mask: Vec<u8>,
+        alignment: u32,
+        first_only: bool,
+    },
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Event {
-    Read { addr: u32, data: u32 },
-    Write { addr: u32, data: u32 },
+    /// The word at `addr`. A read is reported a word at a time, and the last word of a read that
+    /// ends part way through a word holds only the bytes read.
+    Read {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        data: u64,
+    },
+    Write {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        data: u64,
+        width: Width,
+    },
+    /// The remaining `nbytes` of a read starting at `addr` could not be obtained because the
+    /// response was corrupted or cut short.
+    ReadError {
+        addr: u64,
+        nbytes: u32,
+    },
+    WriteBlock {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        len: u32,
+    },
+    Fill {
+        addr: u64,
+        len: u32,
+    },
+    Copy {
+        src: u64,
+        dst: u64,
+        len: u32,
+    },
+    /// The word at `addr` was changed from `old` to `new` by [`Command::Modify`].
+    Modify {
+        addr: u64,
+        old: u32,
+        new: u32,
+    },
+    /// The word at `addr` matched the condition of [`Command::WaitFor`].
+    WaitFor {
+        addr: u64,
+        value: u32,
+    },
+    /// The word at `addr` did not match the condition of [`Command::WaitFor`] in time. `value` is
+    /// the last value read.
+    WaitTimeout {
+        addr: u64,
+        value: u32,
+    },
+    /// A watched word held `value` when first read. `timestamp` is the host time at which it was
+    /// read.
+    Watched {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        value: u32,
+        timestamp: SystemTime,
+    },
+    /// A watched word changed from `old` to `new`. `timestamp` is the host time at which the new
+    /// value was read.
+    Changed {
+        #[serde(default)]
+        space: AddressSpace,
+        addr: u64,
+        old: u32,
+        new: u32,
+        timestamp: SystemTime,
+    },
+    /// `done` of `total` bytes of a multi-step operation have completed.
+    Progress {
+        done: u32,
+        total: u32,
+    },
+    /// The result of [`Command::Crc32`].
+    Crc32 {
+        addr: u64,
+        len: u32,
+        crc: u32,
+    },
+    /// `command` was not sent because it breaks the [`AccessPolicy`] given to
+    /// [`UartDap::with_policy`]. Reported in place of the event the command would have caused.
+    AccessDenied {
+        command: Command,
+        violation: Violation,
+    },
+    /// `command` was not sent because the dialect has no command for it, such as a byte write on
+    /// a shell that only writes words. Reported in place of the event the command would have
+    /// caused.
+    Unsupported {
+        command: Command,
+    },
+    /// `command` was not sent because the shell prompt did not return within the response
+    /// timeout after the previous command, as when the target drops the prompt. An empty line is
+    /// sent in its place so that the shell prints the prompt again for the commands after it.
+    PromptTimeout {
+        command: Command,
+    },
+    /// [`Command::Undo`] restored the previous values of `count` writes.
+    Undo {
+        count: u32,
+    },
+    /// [`Command::Search`] found its pattern at `addr`.
+    Found {
+        addr: u64,
+    },
+    /// [`Command::Search`] of `len` bytes starting at `addr` finished after finding `matches`
+    /// matches.
+    Search {
+        addr: u64,
+        len: u32,
+        matches: u32,
+    },
+}
+
+/// Where formatted commands go.
+enum Link {
+    Serial(SerialStream),
+    /// Commands are written to a transcript and never sent.
+    DryRun(Box<dyn Write + Send>),
 }
 
 // UART Debug Access Port
 pub struct UartDap {
-    port: SerialStream,
+    link: Link,
     echo: Echo,
     line_ending: LineEnding,
+    dialect: Dialect,
+    credentials: Option<Credentials>,
+    endianness: Endianness,
+    read_retries: u32,
+    response_timeout: Duration,
+    statistics: StatisticsHandle,
+    journal: Option<PathBuf>,
+    policy: AccessPolicy,
+    alignment: Alignment,
 }
 
 impl UartDap {
     pub fn new(path: &str, baud_rate: u32, echo: Echo, line_ending: LineEnding) -> Result<Self> {
         let port = tokio_serial::new(path, baud_rate).open_native_async()?;
+        Ok(Self::with_link(Link::Serial(port), echo, line_ending))
+    }
 
-        Ok(Self {
-            port,
+    /// Creates a DAP that writes the text it would send for each command to `transcript`,
+    /// followed by `line_ending`, instead of opening a serial port. Every command completes at
+    /// once as if the target had carried it out, and reads return zeros.
+    pub fn dry_run(transcript: impl Write + Send + 'static, line_ending: LineEnding) -> Self {
+        Self::with_link(
+            Link::DryRun(Box::new(transcript)),
+            Echo::Remote,
+            line_ending,
+        )
+    }
+
+    fn with_link(link: Link, echo: Echo, line_ending: LineEnding) -> Self {
+        Self {
+            link,
             echo,
             line_ending,
-        })
+            dialect: Dialect::default(),
+            credentials: None,
+            endianness: Endianness::default(),
+            read_retries: 0,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            statistics: StatisticsHandle::default(),
+            journal: None,
+            policy: AccessPolicy::default(),
+            alignment: Alignment::default(),
+        }
+    }
+
+    /// Sets the shell dialect of the target. Defaults to [`Dialect::integrity`].
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Logs in with `credentials` whenever the target shows a login prompt, including after a
+    /// reset. Commands are held until the shell prompt appears.
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Sets the byte order of the target. Defaults to [`Endianness::Big`].
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Sets how many times the remainder of a read is requested again after a corrupted response
+    /// line. Defaults to 0.
+    pub fn with_read_retries(mut self, read_retries: u32) -> Self {
+        self.read_retries = read_retries;
+        self
+    }
+
+    /// Sets how long the target may stay silent while a read or CRC-32 result is pending before
+    /// the response is given up with [`Event::ReadError`], and how long a command is held for the
+    /// prompt to return after the previous one before it is given up with
+    /// [`Event::PromptTimeout`]. Defaults to 10 seconds.
+    pub fn with_response_timeout(mut self, response_timeout: Duration) -> Self {
+        self.response_timeout = response_timeout;
+        self
+    }
+
+    /// Reads the value of each word before [`Command::Write`] or [`Command::Modify`] replaces it
+    /// and appends both values to the journal file at `path`, so that [`Command::Undo`] can
+    /// restore them. Disabled by default.
+    pub fn with_journal(mut self, path: impl Into<PathBuf>) -> Self {
+        self.journal = Some(path.into());
+        self
+    }
+
+    /// Refuses commands that would access memory outside `policy` with [`Event::AccessDenied`]
+    /// instead of sending them. Defaults to [`AccessPolicy::allow_all`].
+    pub fn with_policy(mut self, policy: AccessPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets how word accesses that do not start on a word boundary are handled. Defaults to
+    /// [`Alignment::Unchecked`].
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Returns a handle for observing the console statistics while the DAP is running.
+    pub fn statistics(&self) -> StatisticsHandle {
+        self.statistics.clone()
+    }
+
+    /// Returns a handle for typed access to target memory through the channels passed to
+    /// [`UartDap::run`], storing values in the byte order set with [`UartDap::with_endianness`]
+    /// and writing them with the commands of the dialect.
+    pub fn handle<'a>(
+        &self,
+        command_tx: &'a mpsc::Sender<Command>,
+        event_rx: &'a mut mpsc::Receiver<Event>,
+    ) -> DapHandle<'a> {
+        DapHandle::new(
+            command_tx,
+            event_rx,
+            self.endianness,
+            self.dialect.clone(),
+            self.alignment,
+        )
     }
 
     pub async fn run(
@@ -83,17 +586,72 @@ impl UartDap {
         app_command_rx: mpsc::Receiver<Command>,
         serial_event_tx: mpsc::Sender<Event>,
     ) -> Result<()> {
-        let (mut serial_rx, serial_tx) = tokio::io::split(self.port);
+        let (command_tx, command_rx) = mpsc::channel(1);
+        let (response_tx, response_rx) = mpsc::channel(1);
+        let journal = self.journal.as_deref().map(Journal::open).transpose()?;
+        let command_retry_tx = command_tx.clone();
+        let executor = Executor::new(
+            self.dialect.clone(),
+            self.endianness,
+            self.alignment,
+            journal,
+            command_tx,
+            response_rx,
+            serial_event_tx,
+        );
+
+        let port = match self.link {
+            Link::Serial(port) => port,
+            Link::DryRun(transcript) => {
+                let splitter = Splitter {
+                    dialect: &self.dialect,
+                    policy: &self.policy,
+                    endianness: self.endianness,
+                    echo: self.echo,
+                };
+                return tokio::select! {
+                    result = executor.run(app_command_rx) => result,
+                    result = dry_run::transmitter(splitter, self.line_ending, command_rx, response_tx, transcript) => result,
+                };
+            }
+        };
+        let (mut serial_rx, serial_tx) = tokio::io::split(port);
 
         let (command_echo_tx, mut command_echo_rx) = mpsc::channel(1);
         let (command_serial_tx, command_serial_rx) = mpsc::channel(1);
+        let (keystroke_tx, keystroke_rx) = mpsc::channel(1);
+        let (ready_tx, _) = watch::channel(match self.credentials {
+            Some(_) => Ready::LoggingIn,
+            None => Ready::AtPrompt,
+        });
 
-        let prompt = "DEBUG>";
+        let console = Console {
+            dialect: &self.dialect,
+            credentials: self.credentials.as_ref(),
+            parser: HexdumpParser::new(self.endianness),
+            line_ending: self.line_ending,
+            read_retries: self.read_retries,
+            response_timeout: self.response_timeout,
+            statistics: &self.statistics,
+        };
+        let command_response_tx = response_tx.clone();
+        let wake_up = WakeUp {
+            keystroke_tx: keystroke_tx.clone(),
+            line_ending: self.line_ending,
+            timeout: self.response_timeout,
+        };
+        let splitter = Splitter {
+            dialect: &self.dialect,
+            policy: &self.policy,
+            endianness: self.endianness,
+            echo: self.echo,
+        };
 
         tokio::select! {
-            result = command_splitter(app_command_rx, command_echo_tx, command_serial_tx, self.echo) => result,
-            result = serial_transmitter(self.line_ending, command_serial_rx, serial_tx) => result,
-            result = serial_combiner(prompt, self.line_ending, &mut command_echo_rx, &mut serial_rx, serial_event_tx) => result,
+            result = executor.run(app_command_rx) => result,
+            result = command_splitter(splitter, wake_up, command_rx, command_echo_tx, command_serial_tx, command_response_tx, &ready_tx) => result,
+            result = serial_transmitter(self.line_ending, command_serial_rx, keystroke_rx, serial_tx) => result,
+            result = serial_combiner(console, &mut command_echo_rx, command_retry_tx, keystroke_tx, &ready_tx, &mut serial_rx, response_tx) => result,
         }?;
 
         Ok(())
@@ -101,50 +659,430 @@ impl UartDap {
 }
 
 impl Command {
+    /// Sets the bits of the kernel word at `addr` that are set in `bits`.
+    pub fn set_bits(addr: u64, bits: u32) -> Self {
+        Self::Modify {
+            space: AddressSpace::kernel(),
+            addr,
+            mask: bits,
+            value: bits,
+        }
+    }
+
+    /// Clears the bits of the kernel word at `addr` that are set in `bits`.
+    pub fn clear_bits(addr: u64, bits: u32) -> Self {
+        Self::Modify {
+            space: AddressSpace::kernel(),
+            addr,
+            mask: bits,
+            value: 0,
+        }
+    }
+
+    /// Undoes the most recent `count` journaled writes.
+    pub fn undo(count: u32) -> Self {
+        Self::Undo { count: Some(count) }
+    }
+
+    /// Undoes every journaled write.
+    pub fn undo_all() -> Self {
+        Self::Undo { count: None }
+    }
+
+    /// Searches kernel memory for `value` stored with `endianness` in the low `width` bytes of
+    /// naturally aligned elements.
+    pub fn search_value(
+        addr: u64,
+        len: u32,
+        value: u64,
+        width: Width,
+        endianness: Endianness,
+    ) -> Self {
+        Self::Search {
+            space: AddressSpace::kernel(),
+            addr,
+            len,
+            pattern: endianness.value_to_bytes(value, width.bytes() as usize),
+            mask: Vec::new(),
+            alignment: width.bytes(),
+            first_only: false,
+        }
+    }
+
     pub fn from_tokens(tokens: &[&str]) -> Option<Self> {
         match tokens {
-            ["mr", "kernel", addr, nbytes] => {
-                let addr = parse_based_int(&addr).ok()?;
-                let nbytes = parse_based_int(&nbytes).ok()?;
-                Some(Self::Read { addr, nbytes })
+            ["mr", space, addr, nbytes] => {
+                let addr = parse_based_int(addr).ok()?;
+                let nbytes = parse_based_int(nbytes).ok()?;
+                Some(Self::Read {
+                    space: parse_space(space)?,
+                    addr,
+                    nbytes,
+                })
             }
-            ["mr", "kernel", addr] => {
-                let addr = parse_based_int(&addr).ok()?;
+            ["mr", space, addr] => {
+                let addr = parse_based_int(addr).ok()?;
                 Some(Self::Read {
+                    space: parse_space(space)?,
                     addr,
                     nbytes: READ_DEFAULT_NBYTES,
                 })
             }
-            ["mw", "kernel", addr, data] => {
-                let addr = parse_based_int(&addr).ok()?;
-                let data = parse_based_int(&data).ok()?;
-                Some(Self::Write { addr, data })
+            ["mw", space, addr, data] => {
+                let addr = parse_based_int(addr).ok()?;
+                let (data, width) = parse_write_value(data)?;
+                Some(Self::Write {
+                    space: parse_space(space)?,
+                    addr,
+                    data,
+                    width,
+                })
+            }
+            [verb @ ("mw.b" | "mw.w"), space, addr, data] => {
+                let width = match *verb {
+                    "mw.b" => Width::Byte,
+                    _ => Width::Halfword,
+                };
+                Some(Self::Write {
+                    space: parse_space(space)?,
+                    addr: parse_based_int(addr).ok()?,
+                    data: parse_based_int(data).ok()?,
+                    width,
+                })
+            }
+            ["undo"] => Some(Self::undo(1)),
+            ["undo", "all"] => Some(Self::undo_all()),
+            ["undo", count] => Some(Self::undo(parse_based_int(count).ok()?)),
+            [verb, args @ ..] => {
+                // The verbs carried out by the DAP may name an address space before their
+                // arguments, and otherwise access the kernel's
+                let (space, args) = args
+                    .split_first()
+                    .and_then(|(space, rest)| Some((parse_space(space)?, rest)))
+                    .unwrap_or((AddressSpace::kernel(), args));
+                Self::from_dap_tokens(verb, space, args)
+            }
+            _ => None,
+        }
+    }
+
+    fn from_dap_tokens(verb: &str, space: AddressSpace, args: &[&str]) -> Option<Self> {
+        match (verb, args) {
+            ("fill", [addr, len, pattern, width]) => {
+                let addr = parse_based_int(addr).ok()?;
+                let len = parse_based_int(len).ok()?;
+                let pattern = parse_based_int(pattern).ok()?;
+                let width = Width::from_bytes(parse_based_int(width).ok()?)?;
+                Some(Self::Fill {
+                    space,
+                    addr,
+                    len,
+                    pattern,
+                    width,
+                })
+            }
+            ("copy", [src, dst, len]) => {
+                let src = parse_based_int(src).ok()?;
+                let dst = parse_based_int(dst).ok()?;
+                let len = parse_based_int(len).ok()?;
+                Some(Self::Copy {
+                    space,
+                    src,
+                    dst,
+                    len,
+                })
+            }
+            ("modify", [addr, mask, value]) => {
+                let addr = parse_based_int(addr).ok()?;
+                let mask = parse_based_int(mask).ok()?;
+                let value = parse_based_int(value).ok()?;
+                Some(Self::Modify {
+                    space,
+                    addr,
+                    mask,
+                    value,
+                })
+            }
+            ("set", [addr, bits]) => {
+                let addr = parse_based_int(addr).ok()?;
+                let bits = parse_based_int(bits).ok()?;
+                Some(Self::Modify {
+                    space,
+                    addr,
+                    mask: bits,
+                    value: bits,
+                })
+            }
+            ("clear", [addr, bits]) => {
+                let addr = parse_based_int(addr).ok()?;
+                let bits = parse_based_int(bits).ok()?;
+                Some(Self::Modify {
+                    space,
+                    addr,
+                    mask: bits,
+                    value: 0,
+                })
+            }
+            ("wait", [addr, mask, expected, interval_ms, timeout_ms]) => {
+                let addr = parse_based_int(addr).ok()?;
+                let mask = parse_based_int(mask).ok()?;
+                let expected = parse_based_int(expected).ok()?;
+                let interval = Duration::from_millis(parse_based_int(interval_ms).ok()?);
+                let timeout = Duration::from_millis(parse_based_int(timeout_ms).ok()?);
+                Some(Self::WaitFor {
+                    space,
+                    addr,
+                    mask,
+                    expected,
+                    interval,
+                    timeout,
+                })
+            }
+            ("watch", [addr, nbytes, period_ms]) => {
+                let addr = parse_based_int(addr).ok()?;
+                let nbytes = parse_based_int(nbytes).ok()?;
+                let period = Duration::from_millis(parse_based_int(period_ms).ok()?);
+                Some(Self::Watch {
+                    space,
+                    addr,
+                    nbytes,
+                    period,
+                })
+            }
+            ("unwatch", [addr]) => {
+                let addr = parse_based_int(addr).ok()?;
+                Some(Self::Unwatch { space, addr })
+            }
+            ("crc32", [addr, len]) => {
+                let addr = parse_based_int(addr).ok()?;
+                let len = parse_based_int(len).ok()?;
+                Some(Self::Crc32 { space, addr, len })
+            }
+            ("search", [addr, len, pattern, mask, alignment, flags @ ..]) => {
+                let first_only = match flags {
+                    [] => false,
+                    ["first"] => true,
+                    _ => return None,
+                };
+                Some(Self::Search {
+                    space,
+                    addr: parse_based_int(addr).ok()?,
+                    len: parse_based_int(len).ok()?,
+                    pattern: parse_hex_bytes(pattern)?,
+                    mask: parse_hex_bytes(mask)?,
+                    alignment: parse_based_int(alignment).ok()?,
+                    first_only,
+                })
             }
             _ => None,
         }
     }
 }
 
+// Block writes are shown as big-endian words, and byte and halfword writes with a width suffix.
+// The commands carried out by the DAP itself have no Integrity equivalent and are shown in the
+// form accepted by `Command::from_tokens`. See `Dialect::format_command` for the exact text sent
+// to a given target.
 impl fmt::Display for Command {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::Read { addr, nbytes } => write!(f, "mr kernel {addr:#x} {nbytes}"),
-            Self::Write { addr, data } => write!(f, "mw kernel {addr:#x} {data:#x}"),
+            Self::Read {
+                space,
+                addr,
+                nbytes,
+            } => write!(f, "mr {space} {addr:#x} {nbytes}"),
+            Self::Write {
+                space,
+                addr,
+                data,
+                width,
+            } => {
+                let verb = match width {
+                    Width::Byte => "mw.b",
+                    Width::Halfword => "mw.w",
+                    Width::Word | Width::Doubleword => "mw",
+                };
+                let data = format_write_value(*data, *width);
+                write!(f, "{verb} {space} {addr:#x} {data}")
+            }
+            Self::WriteBlock { space, addr, data } => {
+                write!(f, "mw {space} {addr:#x}")?;
+                for word in data.chunks(4) {
+                    write!(f, " {:#x}", Endianness::Big.word_from_bytes(word))?;
+                }
+                Ok(())
+            }
+            Self::Fill {
+                space,
+                addr,
+                len,
+                pattern,
+                width,
+            } => write!(
+                f,
+                "fill {space} {addr:#x} {len} {pattern:#x} {}",
+                width.bytes()
+            ),
+            Self::Copy {
+                space,
+                src,
+                dst,
+                len,
+            } => write!(f, "copy {space} {src:#x} {dst:#x} {len}"),
+            Self::Modify {
+                space,
+                addr,
+                mask,
+                value,
+            } => write!(f, "modify {space} {addr:#x} {mask:#x} {value:#x}"),
+            Self::WaitFor {
+                space,
+                addr,
+                mask,
+                expected,
+                interval,
+                timeout,
+            } => write!(
+                f,
+                "wait {space} {addr:#x} {mask:#x} {expected:#x} {} {}",
+                interval.as_millis(),
+                timeout.as_millis()
+            ),
+            Self::Watch {
+                space,
+                addr,
+                nbytes,
+                period,
+            } => write!(f, "watch {space} {addr:#x} {nbytes} {}", period.as_millis()),
+            Self::Unwatch { space, addr } => write!(f, "unwatch {space} {addr:#x}"),
+            Self::Crc32 { space, addr, len } => write!(f, "crc32 {space} {addr:#x} {len}"),
+            Self::Undo { count: Some(count) } => write!(f, "undo {count}"),
+            Self::Undo { count: None } => write!(f, "undo all"),
+            Self::Search {
+                space,
+                addr,
+                len,
+                pattern,
+                mask,
+                alignment,
+                first_only,
+            } => {
+                write!(f, "search {space} {addr:#x} {len} ")?;
+                pattern.iter().try_for_each(|b| write!(f, "{b:02x}"))?;
+                f.write_str(" ")?;
+                if mask.is_empty() {
+                    pattern.iter().try_for_each(|_| f.write_str("ff"))?;
+                } else {
+                    mask.iter().try_for_each(|b| write!(f, "{b:02x}"))?;
+                }
+                write!(f, " {alignment}")?;
+                if *first_only {
+                    f.write_str(" first")?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
+/// How commands are turned into console text.
+pub(crate) struct Splitter<'a> {
+    pub(crate) dialect: &'a Dialect,
+    pub(crate) policy: &'a AccessPolicy,
+    pub(crate) endianness: Endianness,
+    pub(crate) echo: Echo,
+}
+
+impl Splitter<'_> {
+    /// Passes `command` back if the policy allows it. Otherwise reports [`Event::AccessDenied`] to
+    /// `response_tx` in place of the response the target would give.
+    pub(crate) async fn admit(
+        &self,
+        command: Command,
+        response_tx: &mpsc::Sender<Event>,
+    ) -> Result<Option<Command>> {
+        match self.policy.check(&command) {
+            Some(violation) => {
+                warn!(?command, %violation, "Refusing command");
+                let event = Event::AccessDenied { command, violation };
+                response_tx.send(event).await?;
+                Ok(None)
+            }
+            None => Ok(Some(command)),
+        }
+    }
+}
+
+/// Whether the shell will accept the next command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ready {
+    AtPrompt,
+    /// A command was sent and the prompt has not returned since
+    AwaitingPrompt,
+    LoggingIn,
+}
+
+/// Asks the shell for a fresh prompt when the prompt after a command does not return in time.
+struct WakeUp {
+    keystroke_tx: mpsc::Sender<Keystrokes>,
+    line_ending: LineEnding,
+    timeout: Duration,
+}
+
 #[tracing::instrument(skip_all)]
 async fn command_splitter(
-    mut app_command_rx: mpsc::Receiver<Command>,
-    command_echo_tx: mpsc::Sender<Command>,
-    command_serial_tx: mpsc::Sender<Command>,
-    echo: Echo,
+    splitter: Splitter<'_>,
+    wake_up: WakeUp,
+    mut command_rx: mpsc::Receiver<Command>,
+    command_echo_tx: mpsc::Sender<String>,
+    command_serial_tx: mpsc::Sender<String>,
+    response_tx: mpsc::Sender<Event>,
+    ready_tx: &watch::Sender<Ready>,
 ) -> Result<()> {
-    while let Some(command) = app_command_rx.recv().await {
+    let echo = splitter.echo;
+    let mut ready_rx = ready_tx.subscribe();
+    while let Some(command) = command_rx.recv().await {
         info!(?command, ?echo, "Received command");
+        let Some(command) = splitter.admit(command, &response_tx).await? else {
+            continue;
+        };
+        // Hold commands while logging in or until the shell prompt returns after the previous
+        // command. A login takes as long as it takes, but a prompt that does not return in time
+        // is taken as lost so that it does not hold every later command.
+        let at_prompt = loop {
+            let wait = ready_rx.wait_for(|&ready| ready == Ready::AtPrompt);
+            let waited = tokio::time::timeout(wake_up.timeout, wait)
+                .await
+                .map(|result| result.map(|_| ()));
+            match waited {
+                Ok(result) => {
+                    result.map_err(|_| "ready state closed")?;
+                    break true;
+                }
+                Err(_) if *ready_rx.borrow() == Ready::LoggingIn => continue,
+                Err(_) => break false,
+            }
+        };
+        // The command is given up, and an empty line makes the shell print its prompt again so
+        // that the next command is echoed after it
+        if !at_prompt {
+            warn!(?command, "Timed out waiting for prompt");
+            let keystrokes = Keystrokes {
+                text: wake_up.line_ending.to_string(),
+                secret: false,
+            };
+            wake_up.keystroke_tx.send(keystrokes).await?;
+            let event = Event::PromptTimeout { command };
+            info!(?event, "Sending event");
+            response_tx.send(event).await?;
+            continue;
+        }
+        ready_tx.send_replace(Ready::AwaitingPrompt);
+        let command = splitter
+            .dialect
+            .format_command(&command, splitter.endianness);
         if echo == Echo::Local {
-            command_echo_tx.send(command).await?;
+            command_echo_tx.send(command.clone()).await?;
         }
         command_serial_tx.send(command).await?;
     }
@@ -155,70 +1093,149 @@ async fn command_splitter(
 #[tracing::instrument(skip_all)]
 async fn serial_transmitter(
     line_ending: LineEnding,
-    mut command_serial_rx: mpsc::Receiver<Command>,
+    mut command_serial_rx: mpsc::Receiver<String>,
+    mut keystroke_rx: mpsc::Receiver<Keystrokes>,
     mut serial_tx: WriteHalf<SerialStream>,
 ) -> Result<()> {
-    while let Some(command) = command_serial_rx.recv().await {
-        info!(
-            data = format!("{command}{line_ending}").as_str(),
-            "Transmitting serial"
-        );
-        serial_tx
-            .write_all(command.to_string().as_bytes())
-            .await
-            .map_err(|_| "could not send")?;
-        serial_tx
-            .write_all(line_ending.to_string().as_bytes())
-            .await
-            .map_err(|_| "could not send")?;
+    loop {
+        tokio::select! {
+            command = command_serial_rx.recv() => {
+                let Some(command) = command else {
+                    return Ok(());
+                };
+                info!(
+                    data = format!("{command}{line_ending}").as_str(),
+                    "Transmitting serial"
+                );
+                serial_tx
+                    .write_all(command.as_bytes())
+                    .await
+                    .map_err(|_| "could not send")?;
+                serial_tx
+                    .write_all(line_ending.to_string().as_bytes())
+                    .await
+                    .map_err(|_| "could not send")?;
+            }
+            Some(keystrokes) = keystroke_rx.recv() => {
+                let data = if keystrokes.secret { "<redacted>" } else { keystrokes.text.as_str() };
+                info!(data, "Transmitting keystrokes");
+                serial_tx
+                    .write_all(keystrokes.text.as_bytes())
+                    .await
+                    .map_err(|_| "could not send")?;
+            }
+        }
     }
-
-    Ok(())
 }
 
 #[derive(Debug, Clone)]
 enum BufferState {
     WaitForCommand,
-    WaitForResponse(Command),
+    WaitForResponse {
+        space: AddressSpace,
+        addr: u64,
+        nbytes: u32,
+        attempt: u32,
+    },
+    /// A corrupted read is retried once the prompt returns
+    RetryOnPrompt {
+        space: AddressSpace,
+        addr: u64,
+        nbytes: u32,
+        attempt: u32,
+    },
+    /// A retried read has been transmitted and its echo is expected
+    RetrySent {
+        space: AddressSpace,
+        addr: u64,
+        nbytes: u32,
+        attempt: u32,
+    },
+    /// The target is computing a CRC-32 and will print the result
+    WaitForCrc32 {
+        addr: u64,
+        len: u32,
+    },
+}
+
+/// Text typed on the console outside of a command.
+struct Keystrokes {
+    text: String,
+    /// Keeps the text out of the logs
+    secret: bool,
+}
+
+struct Console<'a> {
+    dialect: &'a Dialect,
+    credentials: Option<&'a Credentials>,
+    parser: HexdumpParser,
+    line_ending: LineEnding,
+    read_retries: u32,
+    response_timeout: Duration,
+    statistics: &'a StatisticsHandle,
 }
 
 #[tracing::instrument(skip_all)]
 async fn serial_combiner(
-    prompt: &str,
-    line_ending: LineEnding,
-    command_echo_rx: &mut mpsc::Receiver<Command>,
+    console: Console<'_>,
+    command_echo_rx: &mut mpsc::Receiver<String>,
+    command_retry_tx: mpsc::Sender<Command>,
+    keystroke_tx: mpsc::Sender<Keystrokes>,
+    ready_tx: &watch::Sender<Ready>,
     mut serial_rx: impl AsyncRead + Unpin,
     mut event_tx: mpsc::Sender<Event>,
 ) -> Result<()> {
     let mut state = BufferState::WaitForCommand;
     let mut line_buffer = BytesMut::with_capacity(LINE_BUFFER_SIZE);
 
+    let mut login = LoginState::LoggedIn;
+    if console.credentials.is_some() {
+        // Wake up the console so that it shows either a login prompt or the shell prompt
+        login = LoginState::Unknown { attempt: 0 };
+        keystroke_tx
+            .send(Keystrokes {
+                text: console.line_ending.to_string(),
+                secret: false,
+            })
+            .await?;
+    }
+
     loop {
-        tokio::select! {
+        let pending = !matches!(state, BufferState::WaitForCommand);
+
+        // Only output from the target can show that the prompt has returned
+        let from_serial = tokio::select! {
             result = command_echo_rx.recv() => {
-                let command = result.ok_or_else(|| "channel closed")?;
-                let message = format!("{}{}", command, line_ending);
-                line_buffer.put_slice(&message.as_bytes());
+                let command = result.ok_or("channel closed")?;
+                let message = format!("{}{}", command, console.line_ending);
+                line_buffer.put_slice(message.as_bytes());
                 info!(?line_buffer, "Received command");
-                Result::<()>::Ok(())
+                false
             }
             result = serial_rx.read_buf(&mut line_buffer) => {
                 result.map_err(|_| "failed to read from serial port")?;
                 info!(?line_buffer, "Received serial");
-                Result::<()>::Ok(())
+                true
             }
-        }?;
+            _ = tokio::time::sleep(console.response_timeout), if pending => {
+                warn!(?state, "Timed out waiting for response");
+                state = abandon_response(state, &mut event_tx).await?;
+                continue;
+            }
+        };
 
-        trace!(line_buffer = str::from_utf8(&line_buffer)?, "recevied data");
+        trace!(line_buffer = %String::from_utf8_lossy(&line_buffer), "recevied data");
 
         if let Some(b'\n') = line_buffer.last() {
             let lines = line_buffer.deref().split(|b| b == &b'\n');
             for line in lines {
-                let line = str::from_utf8(&line)?.trim();
+                let line = decode_line(line, console.statistics);
+                let line = console.dialect.strip_pager_prompts(&line);
+                let line = line.trim();
                 // TODO: remove this hack that accomodates for split with newline at end creating
                 // an empty array
-                if line != "" {
-                    state = process_line(prompt, state, line, &mut event_tx).await?;
+                if !line.is_empty() {
+                    state = process_line(&console, state, line, &mut event_tx).await?;
                 }
             }
             line_buffer.clear();
@@ -236,22 +1253,135 @@ async fn serial_combiner(
                     .split(|&b| b == b'\n')
                     .collect::<Vec<&[u8]>>();
                 let (full_lines, partial_lines) = lines.split_at(lines.len() - 1);
-                let partial_line = partial_lines
-                    .first()
-                    .unwrap()
-                    .iter()
-                    .copied()
-                    .collect::<Vec<u8>>();
+                let partial_line = partial_lines.first().unwrap().to_vec();
                 for line in full_lines {
-                    let line = str::from_utf8(&line)?.trim();
-                    state = process_line(prompt, state, line, &mut event_tx).await?;
+                    let line = decode_line(line, console.statistics);
+                    let line = console.dialect.strip_pager_prompts(&line);
+                    let line = line.trim();
+                    state = process_line(&console, state, line, &mut event_tx).await?;
                 }
                 line_buffer.clear();
                 line_buffer.put_slice(&partial_line);
             }
         }
 
-        trace!(line = str::from_utf8(&line_buffer)?, "processed lines");
+        // Long output is stalled behind a pager prompt until it is answered. Drop the prompt so
+        // that it does not end up in the next line.
+        let partial_start = line_buffer
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |idx| idx + 1);
+        if let Some(idx) = console
+            .dialect
+            .find_pager_prompt(&line_buffer[partial_start..])
+        {
+            info!("Answering pager prompt");
+            line_buffer.truncate(partial_start + idx);
+            keystroke_tx
+                .send(Keystrokes {
+                    text: console.dialect.pager_continue.clone(),
+                    secret: false,
+                })
+                .await?;
+        }
+
+        let partial_line = decode_line(&line_buffer, &StatisticsHandle::default());
+        let partial_line = partial_line.trim_end();
+
+        if let Some(credentials) = console.credentials {
+            if console.dialect.is_login_prompt(partial_line) {
+                // A login prompt outside of the login sequence means the target was reset
+                let attempt = match login {
+                    LoginState::LoggedIn => 1,
+                    LoginState::SentUsername { attempt }
+                    | LoginState::SentPassword { attempt }
+                    | LoginState::Unknown { attempt } => attempt + 1,
+                };
+                if attempt > MAX_LOGIN_ATTEMPTS {
+                    return Err("login failed".into());
+                }
+                info!(username = credentials.username(), attempt, "Logging in");
+                ready_tx.send_replace(Ready::LoggingIn);
+                state = abandon_response(state, &mut event_tx).await?;
+                line_buffer.truncate(partial_start);
+                keystroke_tx
+                    .send(Keystrokes {
+                        text: format!("{}{}", credentials.username(), console.line_ending),
+                        secret: false,
+                    })
+                    .await?;
+                login = LoginState::SentUsername { attempt };
+            } else if console.dialect.is_password_prompt(partial_line) {
+                let attempt = match login {
+                    LoginState::SentUsername { attempt } => attempt,
+                    LoginState::LoggedIn => 1,
+                    LoginState::SentPassword { attempt } | LoginState::Unknown { attempt } => {
+                        attempt + 1
+                    }
+                };
+                if attempt > MAX_LOGIN_ATTEMPTS {
+                    return Err("login failed".into());
+                }
+                info!(attempt, "Sending password");
+                ready_tx.send_replace(Ready::LoggingIn);
+                state = abandon_response(state, &mut event_tx).await?;
+                line_buffer.truncate(partial_start);
+                keystroke_tx
+                    .send(Keystrokes {
+                        text: format!("{}{}", credentials.password(), console.line_ending),
+                        secret: true,
+                    })
+                    .await?;
+                login = LoginState::SentPassword { attempt };
+            } else if login != LoginState::LoggedIn
+                && partial_line.ends_with(&console.dialect.prompt)
+            {
+                info!("Logged in");
+                login = LoginState::LoggedIn;
+                ready_tx.send_replace(Ready::AtPrompt);
+            }
+        }
+
+        let at_prompt = from_serial
+            && login == LoginState::LoggedIn
+            && partial_line.ends_with(&console.dialect.prompt);
+        // Once the prompt returns the next command may be sent. A read retried after a corrupted
+        // response is queued through the splitter like any other command so that it is held
+        // until then and nothing is sent alongside it.
+        if at_prompt {
+            ready_tx.send_replace(Ready::AtPrompt);
+        }
+        match state {
+            BufferState::RetryOnPrompt {
+                space,
+                addr,
+                nbytes,
+                attempt,
+            } if at_prompt => {
+                let command = Command::Read {
+                    space: space.clone(),
+                    addr,
+                    nbytes,
+                };
+                info!(?command, attempt, "Retrying read");
+                command_retry_tx.send(command).await?;
+                state = BufferState::RetrySent {
+                    space,
+                    addr,
+                    nbytes,
+                    attempt,
+                };
+            }
+            // A target that refuses a command, such as with "invalid address", prints the prompt
+            // in place of the response
+            BufferState::WaitForResponse { .. } | BufferState::WaitForCrc32 { .. } if at_prompt => {
+                info!(?state, "Prompt returned before the response");
+                state = abandon_response(state, &mut event_tx).await?;
+            }
+            _ => {}
+        }
+
+        trace!(line = %String::from_utf8_lossy(&line_buffer), "processed lines");
     }
 }
 
@@ -261,93 +1391,308 @@ async fn serial_combiner(
 // [20220204T044316] c0000010: 03 0a 30 18  00 00 00 00  00 00 00 80  00 07 00 00 |..0.............|
 #[tracing::instrument(skip_all)]
 async fn process_line(
-    prompt: &str,
+    console: &Console<'_>,
     state: BufferState,
     line: &str,
     event_tx: &mut mpsc::Sender<Event>,
 ) -> Result<BufferState> {
     info!(?state, ?line, "Processing line");
     match state {
-        BufferState::WaitForCommand => {
-            let tokens = line.split_ascii_whitespace().collect::<Vec<_>>();
-
-            // Guard against panic on split_at when tokens is empty
-            if tokens.is_empty() {
-                return Ok(state);
+        BufferState::WaitForCommand => process_command_line(console, line, None, event_tx).await,
+        BufferState::RetrySent { .. } => {
+            process_command_line(console, line, Some(state), event_tx).await
+        }
+        BufferState::RetryOnPrompt { addr, nbytes, .. } if is_command_line(console, line) => {
+            // Something else was sent before the retry could be
+            send_read_error(addr, nbytes, event_tx).await?;
+            process_command_line(console, line, None, event_tx).await
+        }
+        BufferState::RetryOnPrompt { .. } => Ok(state),
+        BufferState::WaitForCrc32 { addr, len } => {
+            if is_command_line(console, line) {
+                info!(addr, len, "Response ended early");
+                send_read_error(addr, len, event_tx).await?;
+                return process_command_line(console, line, None, event_tx).await;
             }
 
-            match tokens.split_at(1) {
-                (first, user_tokens) if first == [prompt] => {
-                    if let Some(command) = Command::from_tokens(&user_tokens) {
-                        match command {
-                            Command::Write { addr, data } => {
-                                let event = Event::Write { addr, data };
-                                info!(?event, "Sending event");
-                                event_tx.send(event).await?;
+            match parse_crc32_result(line) {
+                Some(crc) => {
+                    let event = Event::Crc32 { addr, len, crc };
+                    info!(?event, "Sending event");
+                    event_tx.send(event).await?;
+                    Ok(BufferState::WaitForCommand)
+                }
+                None => {
+                    info!(?line, "Ignoring line");
+                    Ok(state)
+                }
+            }
+        }
+        BufferState::WaitForResponse {
+            ref space,
+            addr,
+            nbytes,
+            attempt,
+        } => {
+            if is_command_line(console, line) {
+                info!(addr, nbytes, "Response ended early");
+                send_read_error(addr, nbytes, event_tx).await?;
+                return process_command_line(console, line, None, event_tx).await;
+            }
 
-                                Ok(state)
-                            }
-                            Command::Read { addr: _, nbytes: _ } => {
-                                Ok(BufferState::WaitForResponse(command))
-                            }
-                        }
-                    } else {
-                        Ok(state)
+            let read = match console.parser.parse_expected(line, addr, nbytes as usize) {
+                Ok(read) => read,
+                Err(HexdumpError::NotHexdump) => {
+                    // Unrelated console output interleaved with the response
+                    info!(?line, "Ignoring line");
+                    return Ok(state);
+                }
+                Err(error) => {
+                    warn!(%error, ?line, "Corrupted response");
+                    console.statistics.add_corrupted_response();
+                    if attempt < console.read_retries {
+                        let attempt = attempt + 1;
+                        return Ok(BufferState::RetryOnPrompt {
+                            space: space.clone(),
+                            addr,
+                            nbytes,
+                            attempt,
+                        });
                     }
+                    send_read_error(addr, nbytes, event_tx).await?;
+                    return Ok(BufferState::WaitForCommand);
                 }
-                _ => Ok(state),
+            };
+
+            let endianness = console.parser.endianness();
+            let dwords = read
+                .bytes
+                .chunks(4)
+                .map(|dword_bytes| endianness.word_from_bytes(dword_bytes));
+
+            for (idx, dword) in dwords.enumerate() {
+                let addr = addr + idx as u64 * 4;
+                let data = u64::from(dword);
+                let space = space.clone();
+                let event = Event::Read { space, addr, data };
+                info!(?event, "Sending event");
+                event_tx.send(event).await?;
+            }
+
+            let consumed = read.bytes.len() as u32;
+            if nbytes > consumed {
+                Ok(BufferState::WaitForResponse {
+                    space: space.clone(),
+                    addr: addr + u64::from(consumed),
+                    nbytes: nbytes - consumed,
+                    attempt,
+                })
+            } else {
+                Ok(BufferState::WaitForCommand)
             }
         }
-        BufferState::WaitForResponse(command) => {
-            if_chain! {
-                if let Command::Read { addr, nbytes } = command;
-                if let Some((_, remaining)) = line.split_once(": ");
-                if let Some((remaining, _)) = remaining.split_once(" |");
-                then {
-                    let read_bytes = remaining
-                        .split_ascii_whitespace()
-                        .map(|token| u32::from_str_radix(token, 16))
-                        .collect::<std::result::Result<Vec<_>, ParseIntError>>()?;
-                    let dwords = read_bytes.chunks(4).map(|dword_bytes| {
-                        dword_bytes
-                            .iter()
-                            .rev()
-                            .enumerate()
-                            .fold(0u32, |dword, (idx, byte)| dword | (byte << (idx * 8)))
-                    });
-
-                    for (idx, dword) in dwords.enumerate() {
-                        let addr = addr + (idx as u32 * 4);
-                        let data = dword;
-                        let event = Event::Read { addr, data };
+    }
+}
+
+// Parses the result printed by U-Boot like:
+//
+// crc32 for 00001000 ... 0000100f ==> 4e79f1d3
+fn parse_crc32_result(line: &str) -> Option<u32> {
+    let (_, result) = line.split_once("==>")?;
+    u32::from_str_radix(result.trim(), 16).ok()
+}
+
+fn is_command_line(console: &Console, line: &str) -> bool {
+    line.split_ascii_whitespace().next() == Some(console.dialect.prompt.as_str())
+}
+
+// Handles a line while no response is pending. A pending retry keeps its attempt count when its
+// echo is seen.
+async fn process_command_line(
+    console: &Console<'_>,
+    line: &str,
+    retry: Option<BufferState>,
+    event_tx: &mut mpsc::Sender<Event>,
+) -> Result<BufferState> {
+    let tokens = line.split_ascii_whitespace().collect::<Vec<_>>();
+
+    // Guard against panic on split_at when tokens is empty
+    if tokens.is_empty() {
+        return Ok(BufferState::WaitForCommand);
+    }
+
+    match tokens.split_at(1) {
+        (first, user_tokens) if first == [console.dialect.prompt.as_str()] => {
+            let endianness = console.parser.endianness();
+            if let Some(command) = console.dialect.parse_command(user_tokens, endianness) {
+                match command {
+                    Command::Write {
+                        space,
+                        addr,
+                        data,
+                        width,
+                    } => {
+                        let event = Event::Write {
+                            space,
+                            addr,
+                            data,
+                            width,
+                        };
                         info!(?event, "Sending event");
                         event_tx.send(event).await?;
+
+                        Ok(BufferState::WaitForCommand)
                     }
+                    Command::WriteBlock { space, addr, data } => {
+                        let len = data.len() as u32;
+                        let event = Event::WriteBlock { space, addr, len };
+                        info!(?event, "Sending event");
+                        event_tx.send(event).await?;
 
-                    if nbytes > MAX_BYTES_PER_LINE {
-                        let addr = addr + MAX_BYTES_PER_LINE;
-                        let nbytes = nbytes - MAX_BYTES_PER_LINE;
-                        let command = Command::Read { addr, nbytes };
-                        Ok(BufferState::WaitForResponse(command))
-                    } else {
                         Ok(BufferState::WaitForCommand)
                     }
-                } else {
-                    Ok(BufferState::WaitForCommand)
+                    Command::Fill { addr, len, .. } => {
+                        let event = Event::Fill { addr, len };
+                        info!(?event, "Sending event");
+                        event_tx.send(event).await?;
+
+                        Ok(BufferState::WaitForCommand)
+                    }
+                    Command::Copy { src, dst, len, .. } => {
+                        let event = Event::Copy { src, dst, len };
+                        info!(?event, "Sending event");
+                        event_tx.send(event).await?;
+
+                        Ok(BufferState::WaitForCommand)
+                    }
+                    Command::Read {
+                        space,
+                        addr,
+                        nbytes,
+                    } => {
+                        let attempt = match retry {
+                            Some(BufferState::RetrySent {
+                                space: retry_space,
+                                addr: retry_addr,
+                                nbytes: retry_nbytes,
+                                attempt,
+                            }) if (&retry_space, retry_addr, retry_nbytes)
+                                == (&space, addr, nbytes) =>
+                            {
+                                attempt
+                            }
+                            _ => 0,
+                        };
+                        Ok(BufferState::WaitForResponse {
+                            space,
+                            addr,
+                            nbytes,
+                            attempt,
+                        })
+                    }
+                    Command::Crc32 { addr, len, .. } => Ok(BufferState::WaitForCrc32 { addr, len }),
+                    // Carried out by the executor as reads and writes
+                    Command::Modify { .. }
+                    | Command::Undo { .. }
+                    | Command::Search { .. }
+                    | Command::WaitFor { .. }
+                    | Command::Watch { .. }
+                    | Command::Unwatch { .. } => Ok(BufferState::WaitForCommand),
                 }
+            } else {
+                Ok(BufferState::WaitForCommand)
             }
         }
+        _ => Ok(retry.unwrap_or(BufferState::WaitForCommand)),
+    }
+}
+
+// Gives up on a response that will never arrive
+async fn abandon_response(
+    state: BufferState,
+    event_tx: &mut mpsc::Sender<Event>,
+) -> Result<BufferState> {
+    match state {
+        BufferState::WaitForResponse { addr, nbytes, .. }
+        | BufferState::RetryOnPrompt { addr, nbytes, .. }
+        | BufferState::RetrySent { addr, nbytes, .. }
+        | BufferState::WaitForCrc32 {
+            addr, len: nbytes, ..
+        } => {
+            send_read_error(addr, nbytes, event_tx).await?;
+        }
+        BufferState::WaitForCommand => {}
     }
+    Ok(BufferState::WaitForCommand)
 }
 
-fn parse_based_int(s: &str) -> Result<u32> {
-    if s.starts_with("0x") || s.starts_with("0X") {
+async fn send_read_error(addr: u64, nbytes: u32, event_tx: &mut mpsc::Sender<Event>) -> Result<()> {
+    let event = Event::ReadError { addr, nbytes };
+    info!(?event, "Sending event");
+    event_tx.send(event).await?;
+    Ok(())
+}
+
+// Address space names never start with a digit, so that an address is not taken for one
+pub(crate) fn parse_space(s: &str) -> Option<AddressSpace> {
+    match s.chars().next() {
+        Some(c) if !c.is_ascii_digit() => Some(AddressSpace::new(s)),
+        _ => None,
+    }
+}
+
+/// Parses the value of a write. Values given with more than eight hex digits, or too large for a
+/// word, are written as doublewords and anything else as a word.
+pub(crate) fn parse_write_value(s: &str) -> Option<(u64, Width)> {
+    let data = parse_based_int::<u64>(s).ok()?;
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .map_or(0, str::len);
+    let width = if digits > 8 || u32::try_from(data).is_err() {
+        Width::Doubleword
+    } else {
+        Width::Word
+    };
+    Some((data, width))
+}
+
+/// Formats the value of a write so that [`parse_write_value`] reads back its width. Doublewords
+/// are padded to sixteen hex digits.
+pub(crate) fn format_write_value(data: u64, width: Width) -> String {
+    let data = width.truncate(data);
+    match width {
+        Width::Doubleword => format!("{data:#018x}"),
+        Width::Byte | Width::Halfword | Width::Word => format!("{data:#x}"),
+    }
+}
+
+// Parses a string of hex digit pairs like `deadbeef` into bytes in the order written
+fn parse_hex_bytes(s: &str) -> Option<Vec<u8>> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if s.is_empty() || !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(&s[idx..idx + 2], 16).ok())
+        .collect()
+}
+
+/// Parses a decimal, `0x` hexadecimal, or `0b` binary integer that fits in `T`.
+pub(crate) fn parse_based_int<T>(s: &str) -> Result<T>
+where
+    T: TryFrom<u64>,
+    T::Error: std::error::Error + 'static,
+{
+    let value = if s.starts_with("0x") || s.starts_with("0X") {
         let (_prefix, value) = s.split_at(2);
-        Ok(u32::from_str_radix(value, 16)?)
+        u64::from_str_radix(value, 16)?
     } else if s.starts_with("0b") || s.starts_with("0B") {
         let (_prefix, value) = s.split_at(2);
-        Ok(u32::from_str_radix(value, 2)?)
+        u64::from_str_radix(value, 2)?
     } else {
-        Ok(u32::from_str(s)?)
-    }
+        u64::from_str(s)?
+    };
+    Ok(T::try_from(value)?)
 }
@@ -0,0 +1,115 @@
+//! Loading of memory images into target memory.
+//!
+//! A [`Loader`] writes each [`Segment`] of an image, as read by [`crate::image::read_image`],
+//! through a [`DapHandle`] using block writes, and optionally reads each segment back to check
+//! that it arrived intact.
+
+use tracing::info;
+
+use crate::image::Segment;
+use crate::remote::{self, READ_CHUNK_SIZE};
+use crate::{DapHandle, Result};
+
+/// The outcome of loading a single segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentSummary {
+    pub addr: u64,
+    pub bytes_written: u32,
+    /// Whether the read back matched, or `None` if it was not read back.
+    pub verified: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    pub segments: Vec<SegmentSummary>,
+}
+
+impl LoadReport {
+    pub fn bytes_written(&self) -> u32 {
+        self.segments
+            .iter()
+            .map(|segment| segment.bytes_written)
+            .sum()
+    }
+
+    /// Returns false if any segment was read back and did not match.
+    pub fn is_verified(&self) -> bool {
+        self.segments
+            .iter()
+            .all(|segment| segment.verified != Some(false))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Loader {
+    verify: bool,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads each segment back after writing it. Defaults to false.
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Writes `segments` in order, calling `progress` with the bytes written and the total across
+    /// all segments as the writes proceed.
+    pub async fn load(
+        &self,
+        dap: &mut DapHandle<'_>,
+        segments: &[Segment],
+        mut progress: impl FnMut(u32, u32),
+    ) -> Result<LoadReport> {
+        let total = segments
+            .iter()
+            .map(|segment| segment.data.len() as u32)
+            .sum();
+        let mut report = LoadReport::default();
+        let mut written = 0;
+
+        for segment in segments {
+            let Segment { addr, data } = segment;
+            info!(addr, len = data.len(), "Loading segment");
+            remote::write_block(
+                dap.command_tx,
+                dap.event_rx,
+                *addr,
+                data.clone(),
+                &mut |done| progress(written + done, total),
+            )
+            .await?;
+            written += data.len() as u32;
+
+            let verified = if self.verify {
+                Some(self.read_back(dap, segment).await?)
+            } else {
+                None
+            };
+            report.segments.push(SegmentSummary {
+                addr: *addr,
+                bytes_written: data.len() as u32,
+                verified,
+            });
+        }
+
+        Ok(report)
+    }
+
+    async fn read_back(&self, dap: &mut DapHandle<'_>, segment: &Segment) -> Result<bool> {
+        for (idx, expected) in segment.data.chunks(READ_CHUNK_SIZE as usize).enumerate() {
+            let addr = segment.addr + idx as u64 * u64::from(READ_CHUNK_SIZE);
+            let nbytes = expected.len() as u32;
+            let actual =
+                remote::read_bytes(dap.command_tx, dap.event_rx, addr, nbytes, dap.endianness)
+                    .await?;
+            if actual != expected {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,68 @@
+use std::env;
+use std::fmt;
+
+use crate::Result;
+
+/// Environment variable read by [`Credentials::from_env`] for the username.
+pub const USERNAME_VAR: &str = "UART_DAP_USERNAME";
+/// Environment variable read by [`Credentials::from_env`] for the password.
+pub const PASSWORD_VAR: &str = "UART_DAP_PASSWORD";
+
+/// The most consecutive login attempts before giving up.
+pub(crate) const MAX_LOGIN_ATTEMPTS: u32 = 3;
+
+/// Username and password used to log in to a target console.
+///
+/// The password is never included in `Debug` output or tracing.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    username: String,
+    password: String,
+}
+
+impl Credentials {
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Reads the credentials from the [`USERNAME_VAR`] and [`PASSWORD_VAR`] environment
+    /// variables.
+    pub fn from_env() -> Result<Self> {
+        let username = env::var(USERNAME_VAR).map_err(|e| format!("{USERNAME_VAR}: {e}"))?;
+        let password = env::var(PASSWORD_VAR).map_err(|e| format!("{PASSWORD_VAR}: {e}"))?;
+        Ok(Self::new(username, password))
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub(crate) fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Where the combiner is in the login sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LoginState {
+    /// The shell prompt has been seen and commands may be sent.
+    LoggedIn,
+    /// The username has been sent in answer to a login prompt.
+    SentUsername { attempt: u32 },
+    /// The password has been sent in answer to a password prompt.
+    SentPassword { attempt: u32 },
+    /// Waiting for the console to show either a login prompt or the shell prompt.
+    Unknown { attempt: u32 },
+}
@@ -0,0 +1,248 @@
+//! Restrictions on the memory the DAP may touch.
+//!
+//! An [`AccessPolicy`] is a list of [`Rule`]s over address ranges of an address space. The last
+//! rule covering a byte decides how it may be accessed, and bytes covered by no rule fall back to
+//! the policy default. Every command is checked just before it is sent to the target, after the
+//! DAP has broken it down into reads and writes, so that nothing outside the policy reaches the
+//! console.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AddressSpace, Command, Width};
+
+/// How a range of memory may be accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    ReadWrite,
+    ReadOnly,
+    Deny,
+}
+
+/// Covers `len` bytes starting at `addr` in `space`. Accesses to other address spaces are left to
+/// the rules that name them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub space: AddressSpace,
+    pub addr: u64,
+    pub len: u64,
+    pub access: Access,
+    /// If set, accesses must start and end on multiples of this width, and fills must use it.
+    pub width: Option<Width>,
+}
+
+impl Rule {
+    fn end(&self) -> u128 {
+        u128::from(self.addr) + u128::from(self.len)
+    }
+
+    fn contains(&self, space: &AddressSpace, addr: u128) -> bool {
+        self.space == *space && u128::from(self.addr) <= addr && addr < self.end()
+    }
+}
+
+/// What the DAP does with accesses that do not start on a boundary of their width, such as a
+/// word [`Command::Write`] to an odd address, and with byte and halfword writes on a dialect that
+/// has none. Applied before commands are formatted, and before the
+/// [`AccessPolicy`] is checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Alignment {
+    /// Sends them as they are.
+    #[default]
+    Unchecked,
+    /// Refuses them with [`Violation::Unaligned`].
+    Reject,
+    /// Writes the bytes with naturally aligned byte and halfword writes where the dialect has
+    /// them, and otherwise merges them into the aligned words around them. Reads are sent as they
+    /// are where the dialect reads single bytes, and otherwise widened to start on a word boundary
+    /// and trimmed to the bytes asked for.
+    ///
+    /// Merging reads each surrounding word and writes it back whole, and widening reads bytes
+    /// that were not asked for. Neither is safe on registers with side effects, such as
+    /// read-to-clear status bits or FIFOs, and the [`AccessPolicy`] checks the widened accesses
+    /// rather than the bytes asked for.
+    Split,
+}
+
+/// Why a command was not sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Violation {
+    /// The byte at `addr` may not be accessed.
+    Denied { addr: u64 },
+    /// The byte at `addr` may not be written.
+    ReadOnly { addr: u64 },
+    /// The access touching `addr` is not made with the width the range requires.
+    Width { addr: u64, width: Width },
+    /// The access starting at `addr` is not aligned to `width`. See [`Alignment`].
+    Unaligned { addr: u64, width: Width },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Denied { addr } => write!(f, "access to {addr:#x} is denied"),
+            Self::ReadOnly { addr } => write!(f, "{addr:#x} is read only"),
+            Self::Width { addr, width } => {
+                write!(f, "{addr:#x} requires {}-byte accesses", width.bytes())
+            }
+            Self::Unaligned { addr, width } => {
+                write!(f, "{addr:#x} is not aligned to {} bytes", width.bytes())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPolicy {
+    default: Access,
+    rules: Vec<Rule>,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        Self::allow_all()
+    }
+}
+
+impl AccessPolicy {
+    /// Allows everything not restricted by a later rule.
+    pub fn allow_all() -> Self {
+        Self {
+            default: Access::ReadWrite,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Denies everything not allowed by a later rule.
+    pub fn deny_all() -> Self {
+        Self {
+            default: Access::Deny,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Adds a rule that overrides the default and every earlier rule where they overlap.
+    pub fn with_rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Allows reads and writes of `len` kernel bytes starting at `addr`.
+    pub fn with_allowed(self, addr: u64, len: u64) -> Self {
+        self.with_access(addr, len, Access::ReadWrite)
+    }
+
+    /// Allows only reads of `len` kernel bytes starting at `addr`.
+    pub fn with_read_only(self, addr: u64, len: u64) -> Self {
+        self.with_access(addr, len, Access::ReadOnly)
+    }
+
+    /// Denies any access to `len` kernel bytes starting at `addr`.
+    pub fn with_denied(self, addr: u64, len: u64) -> Self {
+        self.with_access(addr, len, Access::Deny)
+    }
+
+    fn with_access(self, addr: u64, len: u64, access: Access) -> Self {
+        self.with_rule(Rule {
+            space: AddressSpace::kernel(),
+            addr,
+            len,
+            access,
+            width: None,
+        })
+    }
+
+    /// Returns why `command` may not be sent, if it may not.
+    pub fn check(&self, command: &Command) -> Option<Violation> {
+        match command {
+            Command::Read {
+                space,
+                addr,
+                nbytes,
+            } => self.check_range(space, *addr, *nbytes, false, None),
+            Command::Crc32 { space, addr, len } => {
+                self.check_range(space, *addr, *len, false, None)
+            }
+            Command::Write {
+                space, addr, width, ..
+            } => self.check_range(space, *addr, width.bytes(), true, Some(*width)),
+            Command::WriteBlock { space, addr, data } => {
+                self.check_range(space, *addr, data.len() as u32, true, None)
+            }
+            Command::Fill {
+                space,
+                addr,
+                len,
+                width,
+                ..
+            } => self.check_range(space, *addr, *len, true, Some(*width)),
+            Command::Copy {
+                space,
+                src,
+                dst,
+                len,
+            } => self
+                .check_range(space, *src, *len, false, None)
+                .or_else(|| self.check_range(space, *dst, *len, true, None)),
+            // Carried out by the DAP as the commands above
+            Command::Modify { .. }
+            | Command::WaitFor { .. }
+            | Command::Watch { .. }
+            | Command::Unwatch { .. }
+            | Command::Undo { .. }
+            | Command::Search { .. } => None,
+        }
+    }
+
+    // Checks each piece of the range that falls under a single rule or the default. `width` is
+    // the element width of the access where it has one.
+    fn check_range(
+        &self,
+        space: &AddressSpace,
+        addr: u64,
+        len: u32,
+        write: bool,
+        width: Option<Width>,
+    ) -> Option<Violation> {
+        let start = u128::from(addr);
+        let end = start + u128::from(len);
+        let mut bounds = vec![start, end];
+        for rule in self.rules.iter().filter(|rule| rule.space == *space) {
+            bounds.extend(
+                [u128::from(rule.addr), rule.end()]
+                    .into_iter()
+                    .filter(|&bound| start < bound && bound < end),
+            );
+        }
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        for piece in bounds.windows(2) {
+            let piece_addr = piece[0] as u64;
+            let rule = self
+                .rules
+                .iter()
+                .rev()
+                .find(|rule| rule.contains(space, piece[0]));
+            match rule.map_or(self.default, |rule| rule.access) {
+                Access::Deny => return Some(Violation::Denied { addr: piece_addr }),
+                Access::ReadOnly if write => return Some(Violation::ReadOnly { addr: piece_addr }),
+                _ => {}
+            }
+            let Some(required) = rule.and_then(|rule| rule.width) else {
+                continue;
+            };
+            let size = required.bytes();
+            let aligned = addr.is_multiple_of(size.into()) && len.is_multiple_of(size);
+            if !aligned || width.is_some_and(|width| width != required) {
+                return Some(Violation::Width {
+                    addr: piece_addr,
+                    width: required,
+                });
+            }
+        }
+
+        None
+    }
+}
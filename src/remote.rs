@@ -0,0 +1,202 @@
+//! Helpers for driving a running [`crate::UartDap`] through its command and event channels.
+
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::{AddressSpace, Command, Endianness, Event, Result, Width};
+
+/// Bytes requested by each read of a longer range.
+pub(crate) const READ_CHUNK_SIZE: u32 = 256;
+
+/// Reads `nbytes` starting at `addr` and returns them in memory order. `endianness` must match
+/// the one the DAP uses to form the words of read events.
+pub(crate) async fn read_bytes(
+    command_tx: &mpsc::Sender<Command>,
+    event_rx: &mut mpsc::Receiver<Event>,
+    addr: u64,
+    nbytes: u32,
+    endianness: Endianness,
+) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(nbytes as usize);
+    match read_into(command_tx, event_rx, addr, nbytes, endianness, &mut bytes).await? {
+        Some(error_addr) => Err(format!("could not read memory at {error_addr:#x}").into()),
+        None => Ok(bytes),
+    }
+}
+
+/// Reads `nbytes` starting at `addr` and appends them to `bytes` in memory order as they arrive,
+/// so that the bytes read before a failure are kept. Returns the address the target reported it
+/// could not read, if any.
+pub(crate) async fn read_into(
+    command_tx: &mpsc::Sender<Command>,
+    event_rx: &mut mpsc::Receiver<Event>,
+    addr: u64,
+    nbytes: u32,
+    endianness: Endianness,
+    bytes: &mut Vec<u8>,
+) -> Result<Option<u64>> {
+    let space = AddressSpace::kernel();
+    command_tx
+        .send(Command::Read {
+            space,
+            addr,
+            nbytes,
+        })
+        .await?;
+
+    let start = bytes.len();
+    while bytes.len() - start < nbytes as usize {
+        match event_rx.recv().await.ok_or("event channel closed")? {
+            Event::Read {
+                addr: read_addr,
+                data,
+                ..
+            } if read_addr == addr + (bytes.len() - start) as u64 => {
+                let len = (nbytes as usize - (bytes.len() - start)).min(4);
+                bytes.extend(endianness.value_to_bytes(data, len));
+            }
+            Event::ReadError {
+                addr: error_addr, ..
+            } => return Ok(Some(error_addr)),
+            event => unexpected(event)?,
+        }
+    }
+
+    Ok(None)
+}
+
+/// Computes the CRC-32 of `len` bytes starting at `addr` with a [`Command::Crc32`], passing each
+/// progress report on to `progress`.
+pub(crate) async fn crc32(
+    command_tx: &mpsc::Sender<Command>,
+    event_rx: &mut mpsc::Receiver<Event>,
+    addr: u64,
+    len: u32,
+    progress: &mut impl FnMut(u32, u32),
+) -> Result<u32> {
+    let space = AddressSpace::kernel();
+    command_tx.send(Command::Crc32 { space, addr, len }).await?;
+
+    loop {
+        match event_rx.recv().await.ok_or("event channel closed")? {
+            Event::Progress { done, total } => progress(done, total),
+            Event::Crc32 {
+                addr: crc_addr,
+                len: crc_len,
+                crc,
+            } if (crc_addr, crc_len) == (addr, len) => return Ok(crc),
+            Event::ReadError {
+                addr: error_addr, ..
+            } => return Err(format!("could not read memory at {error_addr:#x}").into()),
+            event => unexpected(event)?,
+        }
+    }
+}
+
+/// Writes `data` starting at `addr` with a single [`Command::WriteBlock`], passing each progress
+/// report on to `progress`.
+pub(crate) async fn write_block(
+    command_tx: &mpsc::Sender<Command>,
+    event_rx: &mut mpsc::Receiver<Event>,
+    addr: u64,
+    data: Vec<u8>,
+    progress: &mut impl FnMut(u32),
+) -> Result<()> {
+    let space = AddressSpace::kernel();
+    command_tx
+        .send(Command::WriteBlock { space, addr, data })
+        .await?;
+
+    loop {
+        match event_rx.recv().await.ok_or("event channel closed")? {
+            Event::Progress { done, .. } => progress(done),
+            Event::WriteBlock {
+                addr: written_addr, ..
+            } if written_addr == addr => return Ok(()),
+            Event::ReadError {
+                addr: error_addr, ..
+            } => return Err(format!("could not write memory at {error_addr:#x}").into()),
+            event => unexpected(event)?,
+        }
+    }
+}
+
+/// Writes the low `width` bytes of `data` at `addr` with a single [`Command::Write`].
+pub(crate) async fn write(
+    command_tx: &mpsc::Sender<Command>,
+    event_rx: &mut mpsc::Receiver<Event>,
+    addr: u64,
+    data: u64,
+    width: Width,
+) -> Result<()> {
+    let space = AddressSpace::kernel();
+    command_tx
+        .send(Command::Write {
+            space,
+            addr,
+            data,
+            width,
+        })
+        .await?;
+
+    loop {
+        match event_rx.recv().await.ok_or("event channel closed")? {
+            Event::Write {
+                addr: written_addr, ..
+            } if written_addr == addr => return Ok(()),
+            event => unexpected(event)?,
+        }
+    }
+}
+
+/// Fills `len` bytes at `addr` with copies of `pattern` using a [`Command::Fill`].
+pub(crate) async fn fill(
+    command_tx: &mpsc::Sender<Command>,
+    event_rx: &mut mpsc::Receiver<Event>,
+    addr: u64,
+    len: u32,
+    pattern: u64,
+    width: Width,
+) -> Result<()> {
+    let space = AddressSpace::kernel();
+    command_tx
+        .send(Command::Fill {
+            space,
+            addr,
+            len,
+            pattern,
+            width,
+        })
+        .await?;
+
+    loop {
+        match event_rx.recv().await.ok_or("event channel closed")? {
+            Event::Progress { .. } => {}
+            Event::Fill {
+                addr: filled_addr, ..
+            } if filled_addr == addr => return Ok(()),
+            Event::ReadError {
+                addr: error_addr, ..
+            } => return Err(format!("could not write memory at {error_addr:#x}").into()),
+            event => unexpected(event)?,
+        }
+    }
+}
+
+// Fails on an event reported in place of the response to a command that was not carried out, and
+// ignores any other event
+fn unexpected(event: Event) -> Result<()> {
+    match event {
+        Event::AccessDenied { violation, .. } => Err(violation.to_string().into()),
+        Event::Unsupported { command } => {
+            Err(format!("the dialect cannot carry out {command}").into())
+        }
+        Event::PromptTimeout { command } => {
+            Err(format!("the prompt did not return in time to send {command}").into())
+        }
+        event => {
+            warn!(?event, "Ignoring unexpected event");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Periodic sampling of registers into a time series.
+//!
+//! A [`Sampler`] reads a list of words at a fixed interval through the command and event channels
+//! of a running [`crate::UartDap`] and writes one row per sample, stamped with the host time at
+//! which the sample was taken:
+//!
+//! ```text
+//! timestamp,0x1000,0x1004
+//! 1697640000.125000,0x00000001,0x00000000
+//! ```
+//!
+//! ```text
+//! {"timestamp":1697640000.125,"values":{"0x1000":1,"0x1004":0}}
+//! ```
+//!
+//! A value that could not be read is left empty in CSV and is `null` in JSON Lines. Samples can
+//! also be recorded as waveforms with [`Sampler::run_vcd`].
+
+use std::io::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Serializer};
+use tokio::sync::mpsc;
+use tokio::time::{self, MissedTickBehavior};
+use tracing::info;
+
+use crate::remote;
+use crate::vcd::VcdWriter;
+use crate::{AddressSpace, Command, Dialect, Endianness, Event, Result};
+
+/// Bits on the wire per character: a start bit, eight data bits, and a stop bit.
+const BITS_PER_CHAR: u32 = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SampleFormat {
+    #[default]
+    Csv,
+    JsonLines,
+}
+
+/// How well a sampling run kept up with the requested interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleReport {
+    /// Samples written.
+    pub samples: u64,
+    /// Values that could not be read.
+    pub read_errors: u64,
+    /// Samples per second asked for.
+    pub requested_rate: f64,
+    /// Samples per second actually taken.
+    pub achieved_rate: f64,
+    /// The most samples per second the console can carry, if it was described with
+    /// [`Sampler::with_console`].
+    pub max_rate: Option<f64>,
+}
+
+/// A JSON Lines row, with the values keyed by address in the order they were sampled.
+#[derive(Serialize)]
+struct JsonRow<'a> {
+    timestamp: f64,
+    #[serde(serialize_with = "serialize_values")]
+    values: &'a [(String, Option<u32>)],
+}
+
+fn serialize_values<S: Serializer>(
+    values: &[(String, Option<u32>)],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_map(values.iter().map(|(addr, value)| (addr, value)))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sampler {
+    addrs: Vec<u64>,
+    interval: Duration,
+    format: SampleFormat,
+    console: Option<(u32, Dialect)>,
+}
+
+impl Sampler {
+    /// Samples the word at each of `addrs` every `interval`.
+    pub fn new(addrs: Vec<u64>, interval: Duration) -> Self {
+        Self {
+            addrs,
+            interval,
+            format: SampleFormat::default(),
+            console: None,
+        }
+    }
+
+    /// Sets the output format. Defaults to [`SampleFormat::Csv`].
+    pub fn with_format(mut self, format: SampleFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Describes the console the samples are read over, so that [`SampleReport::max_rate`] is
+    /// reported. See [`Sampler::max_rate`].
+    pub fn with_console(mut self, baud_rate: u32, dialect: Dialect) -> Self {
+        self.console = Some((baud_rate, dialect));
+        self
+    }
+
+    /// Samples per second asked for.
+    pub fn requested_rate(&self) -> f64 {
+        1.0 / self.interval.as_secs_f64()
+    }
+
+    /// Estimates the most samples per second a console running at `baud_rate` can carry.
+    ///
+    /// Each read costs its command, the echo of that command, a single line response, and the
+    /// prompt that follows it.
+    pub fn max_rate(&self, baud_rate: u32, dialect: &Dialect) -> f64 {
+        let chars_per_sample = self
+            .addrs
+            .iter()
+            .map(|&addr| {
+                let command = Command::Read {
+                    space: AddressSpace::kernel(),
+                    addr,
+                    nbytes: 4,
+                };
+                let command = dialect.format_command(&command, Endianness::default());
+                let response = format!("{addr:08x}: 00 00 00 00 |....|");
+                // Each line is followed by a line ending of up to two characters
+                2 * (command.len() + 2) + response.len() + 2 + dialect.prompt.len() + 1
+            })
+            .sum::<usize>();
+        f64::from(baud_rate) / f64::from(BITS_PER_CHAR) / chars_per_sample as f64
+    }
+
+    /// Takes `samples` samples and writes them to `writer`, preceded by a header row for CSV.
+    ///
+    /// Events other than the responses to the sampling reads are dropped while sampling.
+    pub async fn run(
+        &self,
+        command_tx: &mpsc::Sender<Command>,
+        event_rx: &mut mpsc::Receiver<Event>,
+        mut writer: impl Write,
+        samples: u64,
+    ) -> Result<SampleReport> {
+        if self.format == SampleFormat::Csv {
+            let header = self
+                .addrs
+                .iter()
+                .map(|addr| format!(",{addr:#x}"))
+                .collect::<String>();
+            writeln!(writer, "timestamp{header}")?;
+        }
+
+        let report = self
+            .sample(command_tx, event_rx, samples, |timestamp, values| {
+                let timestamp = timestamp.duration_since(UNIX_EPOCH)?;
+                self.write_row(&mut writer, timestamp, values)
+            })
+            .await?;
+        writer.flush()?;
+        Ok(report)
+    }
+
+    /// Takes `samples` samples and records them in `vcd`. The output format is ignored.
+    pub async fn run_vcd<W: Write>(
+        &self,
+        command_tx: &mpsc::Sender<Command>,
+        event_rx: &mut mpsc::Receiver<Event>,
+        vcd: &mut VcdWriter<W>,
+        samples: u64,
+    ) -> Result<SampleReport> {
+        self.sample(command_tx, event_rx, samples, |timestamp, values| {
+            for (&addr, value) in self.addrs.iter().zip(values) {
+                if let Some(value) = *value {
+                    vcd.record(addr, value, timestamp)?;
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    // Passes each sample to `record` along with the host time at which it was taken
+    async fn sample(
+        &self,
+        command_tx: &mpsc::Sender<Command>,
+        event_rx: &mut mpsc::Receiver<Event>,
+        samples: u64,
+        mut record: impl FnMut(SystemTime, &[Option<u32>]) -> Result<()>,
+    ) -> Result<SampleReport> {
+        let mut interval = time::interval(self.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut read_errors = 0;
+        let start = Instant::now();
+
+        for _ in 0..samples {
+            interval.tick().await;
+            let timestamp = SystemTime::now();
+
+            let mut values = Vec::with_capacity(self.addrs.len());
+            for &addr in &self.addrs {
+                let value = read_word(command_tx, event_rx, addr).await?;
+                if value.is_none() {
+                    read_errors += 1;
+                }
+                values.push(value);
+            }
+
+            record(timestamp, &values)?;
+        }
+
+        let elapsed = start.elapsed().as_secs_f64();
+        let report = SampleReport {
+            samples,
+            read_errors,
+            requested_rate: self.requested_rate(),
+            achieved_rate: if elapsed > 0.0 {
+                samples as f64 / elapsed
+            } else {
+                0.0
+            },
+            max_rate: self
+                .console
+                .as_ref()
+                .map(|(baud_rate, dialect)| self.max_rate(*baud_rate, dialect)),
+        };
+        info!(?report, "Sampling done");
+        Ok(report)
+    }
+
+    fn write_row(
+        &self,
+        writer: &mut impl Write,
+        timestamp: Duration,
+        values: &[Option<u32>],
+    ) -> Result<()> {
+        match self.format {
+            SampleFormat::Csv => {
+                let timestamp = format!("{}.{:06}", timestamp.as_secs(), timestamp.subsec_micros());
+                let values = values
+                    .iter()
+                    .map(|value| value.map_or(",".to_string(), |value| format!(",{value:#010x}")))
+                    .collect::<String>();
+                writeln!(writer, "{timestamp}{values}")?;
+            }
+            SampleFormat::JsonLines => {
+                let values = self
+                    .addrs
+                    .iter()
+                    .zip(values)
+                    .map(|(addr, value)| (format!("{addr:#x}"), *value))
+                    .collect::<Vec<_>>();
+                let row = JsonRow {
+                    timestamp: timestamp.as_secs_f64(),
+                    values: &values,
+                };
+                serde_json::to_writer(&mut *writer, &row)?;
+                writeln!(writer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Returns `None` if the target could not provide the word
+async fn read_word(
+    command_tx: &mpsc::Sender<Command>,
+    event_rx: &mut mpsc::Receiver<Event>,
+    addr: u64,
+) -> Result<Option<u32>> {
+    // The word is split into bytes and formed again in the same order, so either order will do
+    let endianness = Endianness::default();
+    let mut bytes = Vec::with_capacity(4);
+    let failed = remote::read_into(command_tx, event_rx, addr, 4, endianness, &mut bytes).await?;
+    Ok(failed.is_none().then(|| endianness.word_from_bytes(&bytes)))
+}
@@ -0,0 +1,120 @@
+//! Snapshots of target memory.
+//!
+//! A [`Snapshot`] captures one or more word-aligned ranges through a [`DapHandle`]. Two snapshots of the same ranges can be diffed word by word,
+//! and a snapshot can be written back to restore the captured values.
+
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::remote::{self, READ_CHUNK_SIZE};
+use crate::{DapHandle, Endianness, Result, Width};
+
+/// The captured contents of a range of memory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub addr: u64,
+    pub data: Vec<u8>,
+}
+
+/// A word whose value differs between two snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WordChange {
+    pub addr: u64,
+    pub old: u32,
+    pub new: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Host time at which the capture started.
+    pub timestamp: SystemTime,
+    /// The byte order of the DAP that captured the snapshot, used to form words from its bytes.
+    pub endianness: Endianness,
+    pub regions: Vec<Region>,
+}
+
+impl Snapshot {
+    /// Reads each `(addr, len)` range. Ranges must start and end on word boundaries.
+    pub async fn capture(dap: &mut DapHandle<'_>, ranges: &[(u64, u32)]) -> Result<Self> {
+        if let Some((addr, len)) = ranges
+            .iter()
+            .find(|(addr, len)| !addr.is_multiple_of(4) || !len.is_multiple_of(4))
+        {
+            return Err(format!("range {addr:#x}+{len:#x} is not word aligned").into());
+        }
+
+        let timestamp = SystemTime::now();
+        let mut regions = Vec::with_capacity(ranges.len());
+        for &(addr, len) in ranges {
+            info!(addr, len, "Capturing region");
+            let mut data = Vec::with_capacity(len as usize);
+            for offset in (0..len).step_by(READ_CHUNK_SIZE as usize) {
+                let nbytes = (len - offset).min(READ_CHUNK_SIZE);
+                data.extend(
+                    remote::read_bytes(
+                        dap.command_tx,
+                        dap.event_rx,
+                        addr + u64::from(offset),
+                        nbytes,
+                        dap.endianness,
+                    )
+                    .await?,
+                );
+            }
+            regions.push(Region { addr, data });
+        }
+
+        Ok(Self {
+            timestamp,
+            endianness: dap.endianness,
+            regions,
+        })
+    }
+
+    /// Returns the words that differ from `later` in address order. Only regions captured at the
+    /// same address with the same length in both snapshots are compared.
+    pub fn diff(&self, later: &Snapshot) -> Vec<WordChange> {
+        let mut changes = self
+            .regions
+            .iter()
+            .filter_map(|region| {
+                let other = later.regions.iter().find(|other| {
+                    other.addr == region.addr && other.data.len() == region.data.len()
+                })?;
+                Some((region, other))
+            })
+            .flat_map(|(region, other)| {
+                (region.addr..)
+                    .step_by(4)
+                    .zip(region.data.chunks(4).zip(other.data.chunks(4)))
+                    .filter(|(_, (old, new))| old != new)
+                    .map(|(addr, (old, new))| WordChange {
+                        addr,
+                        old: self.endianness.word_from_bytes(old),
+                        new: later.endianness.word_from_bytes(new),
+                    })
+            })
+            .collect::<Vec<_>>();
+        changes.sort_by_key(|change| change.addr);
+        changes
+    }
+
+    /// Writes every captured word back with [`crate::Command::Write`].
+    pub async fn restore(&self, dap: &mut DapHandle<'_>) -> Result<()> {
+        for region in &self.regions {
+            info!(
+                addr = region.addr,
+                len = region.data.len(),
+                "Restoring region"
+            );
+            for (addr, word) in (region.addr..).step_by(4).zip(region.data.chunks(4)) {
+                let data = self.endianness.word_from_bytes(word);
+                let data = u64::from(data);
+                remote::write(dap.command_tx, dap.event_rx, addr, data, Width::Word).await?;
+            }
+        }
+        Ok(())
+    }
+}
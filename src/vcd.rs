@@ -0,0 +1,213 @@
+//! Value Change Dump export of register values for viewing in waveform viewers such as GTKWave.
+//!
+//! Each [`Signal`] is a whole register or a named bitfield within one. Values are recorded with
+//! the host time at which they were read, either from [`Event::Watched`] and [`Event::Changed`] or
+//! from a [`crate::Sampler`], and only changes are written:
+//!
+//! ```text
+//! $version uart-dap $end
+//! $timescale 1us $end
+//! $scope module uart_dap $end
+//! $var wire 32 ! ctrl $end
+//! $var wire 1 " ctrl_enable $end
+//! $upscope $end
+//! $enddefinitions $end
+//! #0
+//! $dumpvars
+//! bx !
+//! x"
+//! $end
+//! b1 !
+//! 1"
+//! ```
+//!
+//! Times are relative to the first value recorded.
+
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+
+use crate::{Event, Result};
+
+/// Bits in a register.
+const WORD_BITS: u32 = 32;
+
+/// A register, or a bitfield within one, shown as a single waveform.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signal {
+    name: String,
+    addr: u64,
+    lsb: u32,
+    width: u32,
+}
+
+impl Signal {
+    /// The whole word at `addr`.
+    pub fn register(name: impl Into<String>, addr: u64) -> Self {
+        Self {
+            name: name.into(),
+            addr,
+            lsb: 0,
+            width: WORD_BITS,
+        }
+    }
+
+    /// The `width` bits of the word at `addr` starting at bit `lsb`. Fails unless the bitfield
+    /// has at least one bit and lies within the word.
+    pub fn bitfield(name: impl Into<String>, addr: u64, lsb: u32, width: u32) -> Result<Self> {
+        let name = name.into();
+        match lsb.checked_add(width) {
+            Some(end) if width > 0 && end <= WORD_BITS => Ok(Self {
+                name,
+                addr,
+                lsb,
+                width,
+            }),
+            _ => Err(
+                format!("bitfield {name} of {width} bits at bit {lsb} is not within a word").into(),
+            ),
+        }
+    }
+
+    fn extract(&self, word: u32) -> u32 {
+        let mask = u32::MAX
+            .checked_shr(32u32.saturating_sub(self.width))
+            .unwrap_or(0);
+        word.checked_shr(self.lsb).unwrap_or(0) & mask
+    }
+}
+
+pub struct VcdWriter<W: Write> {
+    writer: W,
+    signals: Vec<Signal>,
+    values: Vec<Option<u32>>,
+    /// Host time of the first value recorded
+    start: Option<SystemTime>,
+    /// The most recent time written, in microseconds since `start`
+    time: Option<u128>,
+}
+
+impl<W: Write> VcdWriter<W> {
+    /// Writes the header declaring `signals` to `writer`.
+    pub fn new(mut writer: W, signals: Vec<Signal>) -> Result<Self> {
+        writeln!(writer, "$version uart-dap $end")?;
+        writeln!(writer, "$timescale 1us $end")?;
+        writeln!(writer, "$scope module uart_dap $end")?;
+        for (idx, signal) in signals.iter().enumerate() {
+            let name = signal.name.replace(char::is_whitespace, "_");
+            writeln!(
+                writer,
+                "$var wire {} {} {name} $end",
+                signal.width,
+                identifier(idx)
+            )?;
+        }
+        writeln!(writer, "$upscope $end")?;
+        writeln!(writer, "$enddefinitions $end")?;
+
+        let values = vec![None; signals.len()];
+        Ok(Self {
+            writer,
+            signals,
+            values,
+            start: None,
+            time: None,
+        })
+    }
+
+    /// Records that the word at `addr` held `word` at `timestamp`.
+    ///
+    /// Timestamps earlier than one already recorded are treated as the latest time recorded.
+    pub fn record(&mut self, addr: u64, word: u32, timestamp: SystemTime) -> Result<()> {
+        let start = *self.start.get_or_insert(timestamp);
+        let time = timestamp
+            .duration_since(start)
+            .unwrap_or(Duration::ZERO)
+            .as_micros();
+
+        let changes = self
+            .signals
+            .iter()
+            .enumerate()
+            .filter(|(_, signal)| signal.addr == addr)
+            .map(|(idx, signal)| (idx, signal.extract(word)))
+            .filter(|&(idx, value)| self.values[idx] != Some(value))
+            .collect::<Vec<_>>();
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        match self.time {
+            None => {
+                writeln!(self.writer, "#0")?;
+                writeln!(self.writer, "$dumpvars")?;
+                for (idx, signal) in self.signals.iter().enumerate() {
+                    if signal.width == 1 {
+                        writeln!(self.writer, "x{}", identifier(idx))?;
+                    } else {
+                        writeln!(self.writer, "bx {}", identifier(idx))?;
+                    }
+                }
+                writeln!(self.writer, "$end")?;
+                self.time = Some(0);
+            }
+            Some(last) if time > last => {
+                writeln!(self.writer, "#{time}")?;
+                self.time = Some(time);
+            }
+            Some(_) => {}
+        }
+
+        for (idx, value) in changes {
+            if self.signals[idx].width == 1 {
+                writeln!(self.writer, "{value}{}", identifier(idx))?;
+            } else {
+                writeln!(self.writer, "b{value:b} {}", identifier(idx))?;
+            }
+            self.values[idx] = Some(value);
+        }
+
+        Ok(())
+    }
+
+    /// Records the value of an [`Event::Watched`] or the new value of an [`Event::Changed`]. Other
+    /// events are ignored.
+    pub fn record_event(&mut self, event: &Event) -> Result<()> {
+        match *event {
+            Event::Watched {
+                addr,
+                value,
+                timestamp,
+                ..
+            } => self.record(addr, value, timestamp),
+            Event::Changed {
+                addr,
+                new,
+                timestamp,
+                ..
+            } => self.record(addr, new, timestamp),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// Identifier codes are strings of the printable ASCII characters `!` through `~`
+fn identifier(mut idx: usize) -> String {
+    const FIRST: u8 = b'!';
+    const COUNT: usize = (b'~' - b'!' + 1) as usize;
+
+    let mut code = String::new();
+    loop {
+        code.push((FIRST + (idx % COUNT) as u8) as char);
+        idx /= COUNT;
+        if idx == 0 {
+            return code;
+        }
+        idx -= 1;
+    }
+}
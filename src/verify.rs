@@ -0,0 +1,123 @@
+//! Comparison of target memory against a golden image.
+//!
+//! A [`Verifier`] reads the ranges covered by each [`Segment`] of an image through a
+//! [`DapHandle`] and compares them byte by byte.
+
+use std::fmt;
+
+use tracing::info;
+
+use crate::image::Segment;
+use crate::remote::{self, READ_CHUNK_SIZE};
+use crate::{DapHandle, Result};
+
+/// Mismatches recorded by default before the rest are only counted.
+pub const DEFAULT_MAX_MISMATCHES: usize = 10;
+
+/// A byte of target memory that differs from the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mismatch {
+    pub addr: u64,
+    pub expected: u8,
+    pub actual: u8,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#010x}: expected {:#04x}, read {:#04x}",
+            self.addr, self.expected, self.actual
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub bytes_compared: u32,
+    /// The first mismatches in address order, up to [`Verifier::with_max_mismatches`].
+    pub mismatches: Vec<Mismatch>,
+    /// The number of mismatches including those not recorded.
+    pub mismatch_count: u32,
+}
+
+impl VerifyReport {
+    pub fn is_match(&self) -> bool {
+        self.mismatch_count == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verifier {
+    max_mismatches: usize,
+}
+
+impl Default for Verifier {
+    fn default() -> Self {
+        Self {
+            max_mismatches: DEFAULT_MAX_MISMATCHES,
+        }
+    }
+}
+
+impl Verifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of mismatches recorded in the report. Defaults to
+    /// [`DEFAULT_MAX_MISMATCHES`].
+    pub fn with_max_mismatches(mut self, max_mismatches: usize) -> Self {
+        self.max_mismatches = max_mismatches;
+        self
+    }
+
+    /// Reads back every segment in order and compares it against the image, calling `progress`
+    /// with the bytes compared and the total across all segments.
+    pub async fn verify(
+        &self,
+        dap: &mut DapHandle<'_>,
+        segments: &[Segment],
+        mut progress: impl FnMut(u32, u32),
+    ) -> Result<VerifyReport> {
+        let total = segments
+            .iter()
+            .map(|segment| segment.data.len() as u32)
+            .sum();
+        let mut report = VerifyReport::default();
+
+        for segment in segments {
+            info!(
+                addr = segment.addr,
+                len = segment.data.len(),
+                "Verifying segment"
+            );
+            for (idx, expected) in segment.data.chunks(READ_CHUNK_SIZE as usize).enumerate() {
+                let addr = segment.addr + idx as u64 * u64::from(READ_CHUNK_SIZE);
+                let nbytes = expected.len() as u32;
+                let actual =
+                    remote::read_bytes(dap.command_tx, dap.event_rx, addr, nbytes, dap.endianness)
+                        .await?;
+                // Offsets rather than addresses so that a segment ending at the top of the
+                // address space does not overflow
+                for (offset, (&expected, &actual)) in expected.iter().zip(&actual).enumerate() {
+                    if expected != actual {
+                        let addr = addr + offset as u64;
+                        report.mismatch_count += 1;
+                        if report.mismatches.len() < self.max_mismatches {
+                            report.mismatches.push(Mismatch {
+                                addr,
+                                expected,
+                                actual,
+                            });
+                        }
+                    }
+                }
+                report.bytes_compared += nbytes;
+                progress(report.bytes_compared, total);
+            }
+        }
+
+        Ok(report)
+    }
+}
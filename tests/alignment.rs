@@ -0,0 +1,164 @@
+mod common;
+
+use uart_dap::{
+    AddressSpace, Alignment, Command, Dialect, Event, LineEnding, UartDap, Violation, Width,
+};
+
+use common::{run, Transcript};
+
+#[tokio::test]
+async fn splits_into_narrow_writes_on_uboot() {
+    let transcript = Transcript::default();
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::Lf)
+        .with_dialect(Dialect::uboot())
+        .with_alignment(Alignment::Split);
+
+    let commands_and_events = vec![
+        (
+            Command::Write {
+                space: AddressSpace::kernel(),
+                addr: 0x1001,
+                data: 0x11223344,
+                width: Width::Word,
+            },
+            vec![Event::Write {
+                space: AddressSpace::kernel(),
+                addr: 0x1001,
+                data: 0x11223344,
+                width: Width::Word,
+            }],
+        ),
+        (
+            Command::Read {
+                space: AddressSpace::kernel(),
+                addr: 0x2002,
+                nbytes: 4,
+            },
+            vec![Event::Read {
+                space: AddressSpace::kernel(),
+                addr: 0x2002,
+                data: 0,
+            }],
+        ),
+    ];
+    run(dap, commands_and_events).await;
+
+    assert_eq!(
+        transcript.text(),
+        "mw.b 0x1001 0x11 0x1\nmw.w 0x1002 0x2233 0x1\nmw.b 0x1004 0x44 0x1\nmd.b 0x2002 0x4\n"
+    );
+}
+
+#[tokio::test]
+async fn splits_into_read_modify_writes_without_narrow_writes() {
+    let transcript = Transcript::default();
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::Lf).with_alignment(Alignment::Split);
+
+    let commands_and_events = vec![(
+        Command::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x1001,
+            data: 0x11223344,
+            width: Width::Word,
+        },
+        vec![Event::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x1001,
+            data: 0x11223344,
+            width: Width::Word,
+        }],
+    )];
+    run(dap, commands_and_events).await;
+
+    assert_eq!(
+        transcript.text(),
+        "mr kernel 0x1000 4\nmw kernel 0x1000 0x112233\nmr kernel 0x1004 4\nmw kernel 0x1004 0x44000000\n"
+    );
+}
+
+#[tokio::test]
+async fn rejects_unaligned_accesses() {
+    let transcript = Transcript::default();
+    let dap =
+        UartDap::dry_run(transcript.clone(), LineEnding::Lf).with_alignment(Alignment::Reject);
+
+    let write = Command::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x1002,
+        data: 1,
+        width: Width::Word,
+    };
+    let commands_and_events = vec![
+        (
+            write.clone(),
+            vec![Event::AccessDenied {
+                command: write,
+                violation: Violation::Unaligned {
+                    addr: 0x1002,
+                    width: Width::Word,
+                },
+            }],
+        ),
+        (
+            Command::Write {
+                space: AddressSpace::kernel(),
+                addr: 0x1000,
+                data: 1,
+                width: Width::Word,
+            },
+            vec![Event::Write {
+                space: AddressSpace::kernel(),
+                addr: 0x1000,
+                data: 1,
+                width: Width::Word,
+            }],
+        ),
+    ];
+    run(dap, commands_and_events).await;
+
+    assert_eq!(transcript.text(), "mw kernel 0x1000 0x1\n");
+}
+
+#[tokio::test]
+async fn refuses_narrow_writes_without_narrow_writes() {
+    let transcript = Transcript::default();
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::Lf);
+
+    let write = Command::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x1002,
+        data: 0xa5,
+        width: Width::Byte,
+    };
+    let commands_and_events = vec![(write.clone(), vec![Event::Unsupported { command: write }])];
+    run(dap, commands_and_events).await;
+
+    assert_eq!(transcript.text(), "");
+}
+
+#[tokio::test]
+async fn merges_narrow_writes_when_splitting() {
+    let transcript = Transcript::default();
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::Lf).with_alignment(Alignment::Split);
+
+    let commands_and_events = vec![(
+        Command::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x1002,
+            data: 0xa5a5,
+            width: Width::Halfword,
+        },
+        vec![Event::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x1002,
+            data: 0xa5a5,
+            width: Width::Halfword,
+        }],
+    )];
+    run(dap, commands_and_events).await;
+
+    assert_eq!(
+        transcript.text(),
+        "mr kernel 0x1000 4\nmw kernel 0x1000 0xa5a5\n"
+    );
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use uart_dap::{Algorithm, Checksum, Endianness};
+
+use common::FakeTarget;
+
+const DATA: &[u8] = b"123456789";
+
+// Holds `DATA` at 0x1000 and carries out CRC-32s itself. Little endian so that the hashes only
+// match if the handle forms words in the same order.
+fn fake_target() -> FakeTarget {
+    FakeTarget::new(Endianness::Little)
+        .with_bytes(0x1000, DATA)
+        .with_crc32(0xcbf4_3926)
+}
+
+#[tokio::test]
+async fn streams_range_through_host() {
+    let target = fake_target();
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+    let cases = [
+        (Algorithm::Crc16Ccitt, "29b1"),
+        (
+            Algorithm::Sha256,
+            "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225",
+        ),
+        (Algorithm::Additive, "000001dd"),
+    ];
+
+    for (algorithm, expected) in cases {
+        let mut progress = Vec::new();
+        let checksum = Checksum::new(algorithm, 0x1000, DATA.len() as u32)
+            .with_chunk_size(4)
+            .compute(&mut dap, |done, total| progress.push((done, total)))
+            .await
+            .unwrap();
+
+        assert_eq!(checksum.to_string(), expected, "{algorithm}");
+        assert_eq!(progress, [(4, 9), (8, 9), (9, 9)]);
+    }
+}
+
+#[tokio::test]
+async fn computes_crc32_with_command() {
+    let target = fake_target();
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+
+    let checksum = Checksum::new(Algorithm::Crc32, 0x1000, DATA.len() as u32)
+        .compute(&mut dap, |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(checksum.bytes, [0xcb, 0xf4, 0x39, 0x26]);
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use uart_dap::{AddressSpace, Command, Width};
+
+#[test]
+fn deserializes_commands_without_address_space() {
+    let command = serde_json::from_str::<Command>(r#"{"Read":{"addr":4096,"nbytes":4}}"#).unwrap();
+    assert_eq!(
+        command,
+        Command::Read {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            nbytes: 4,
+        }
+    );
+
+    let command = serde_json::from_str::<Command>(
+        r#"{"Fill":{"addr":4096,"len":8,"pattern":0,"width":"Word"}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        command,
+        Command::Fill {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            len: 8,
+            pattern: 0,
+            width: Width::Word,
+        }
+    );
+}
+
+#[test]
+fn parses_address_space_after_verb() {
+    let task = AddressSpace::new("task");
+    let cases = [
+        (
+            "fill task 0x1000 8 0x5a 4",
+            Command::Fill {
+                space: task.clone(),
+                addr: 0x1000,
+                len: 8,
+                pattern: 0x5a,
+                width: Width::Word,
+            },
+        ),
+        (
+            "set task 0x1000 0x4",
+            Command::Modify {
+                space: task.clone(),
+                addr: 0x1000,
+                mask: 0x4,
+                value: 0x4,
+            },
+        ),
+        (
+            "watch task 0x1000 8 100",
+            Command::Watch {
+                space: task.clone(),
+                addr: 0x1000,
+                nbytes: 8,
+                period: Duration::from_millis(100),
+            },
+        ),
+        (
+            "crc32 0x1000 16",
+            Command::Crc32 {
+                space: AddressSpace::kernel(),
+                addr: 0x1000,
+                len: 16,
+            },
+        ),
+    ];
+    for (text, command) in cases {
+        let tokens = text.split_ascii_whitespace().collect::<Vec<_>>();
+        assert_eq!(
+            Command::from_tokens(&tokens).as_ref(),
+            Some(&command),
+            "{text}"
+        );
+
+        let shown = command.to_string();
+        let tokens = shown.split_ascii_whitespace().collect::<Vec<_>>();
+        assert_eq!(Command::from_tokens(&tokens), Some(command), "{shown}");
+    }
+}
@@ -0,0 +1,260 @@
+//! Helpers shared by the tests that run commands through a dry run DAP or a fake target.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc;
+use uart_dap::{Command, DapHandle, Endianness, Event, LineEnding, UartDap, Width};
+
+/// Collects the text a dry run DAP would have sent.
+#[derive(Clone, Default)]
+pub struct Transcript(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Transcript {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transcript {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+// Runs the commands through a dry run DAP, checking the events each one produces
+pub async fn run(dap: UartDap, commands_and_events: Vec<(Command, Vec<Event>)>) {
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    for (command, events) in commands_and_events {
+        command_tx.send(command).await.unwrap();
+        for event in events {
+            assert_eq!(event_rx.recv().await.unwrap(), event);
+        }
+    }
+
+    drop(command_tx);
+    join_handle.await.unwrap();
+}
+
+/// Stands in for a running DAP whose target memory is shared with the test. Bytes that were never
+/// written read as `contents` gives them, and words are formed from bytes with `endianness`.
+/// Every command received is recorded.
+#[derive(Clone)]
+pub struct FakeTarget {
+    endianness: Endianness,
+    contents: fn(u64) -> u8,
+    memory: Arc<Mutex<HashMap<u64, u8>>>,
+    commands: Arc<Mutex<Vec<Command>>>,
+    read_errors: Option<(u64, u32)>,
+    stuck_addr: Option<u64>,
+    crc32: Option<u32>,
+}
+
+impl FakeTarget {
+    /// Memory reads as zero until written.
+    pub fn new(endianness: Endianness) -> Self {
+        Self {
+            endianness,
+            contents: |_| 0,
+            memory: Arc::default(),
+            commands: Arc::default(),
+            read_errors: None,
+            stuck_addr: None,
+            crc32: None,
+        }
+    }
+
+    pub fn with_contents(mut self, contents: fn(u64) -> u8) -> Self {
+        self.contents = contents;
+        self
+    }
+
+    pub fn with_bytes(self, addr: u64, bytes: &[u8]) -> Self {
+        self.store(addr, bytes);
+        self
+    }
+
+    /// The first `count` reads reaching `addr` stop with a read error there.
+    pub fn with_read_errors(mut self, addr: u64, count: u32) -> Self {
+        self.read_errors = Some((addr, count));
+        self
+    }
+
+    /// Writes to `addr` are ignored.
+    pub fn with_stuck_addr(mut self, addr: u64) -> Self {
+        self.stuck_addr = Some(addr);
+        self
+    }
+
+    /// Carries out CRC-32s itself, always answering `crc`.
+    pub fn with_crc32(mut self, crc: u32) -> Self {
+        self.crc32 = Some(crc);
+        self
+    }
+
+    pub fn commands(&self) -> Vec<Command> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// Writes the low `width` bytes of `value` at `addr` behind the DAP's back.
+    pub fn write(&self, addr: u64, value: u64, width: Width) {
+        let bytes = self
+            .endianness
+            .value_to_bytes(value, width.bytes() as usize);
+        self.store(addr, &bytes);
+    }
+
+    pub fn read(&self, addr: u64, width: Width) -> u64 {
+        let bytes = self.load(addr, width.bytes());
+        self.endianness.value_from_bytes(&bytes)
+    }
+
+    /// Returns a handle of a DAP whose byte order matches the target's.
+    pub fn handle<'a>(
+        &self,
+        command_tx: &'a mpsc::Sender<Command>,
+        event_rx: &'a mut mpsc::Receiver<Event>,
+    ) -> DapHandle<'a> {
+        UartDap::dry_run(std::io::sink(), LineEnding::Lf)
+            .with_endianness(self.endianness)
+            .handle(command_tx, event_rx)
+    }
+
+    pub fn spawn(&self) -> (mpsc::Sender<Command>, mpsc::Receiver<Event>) {
+        let (command_tx, mut command_rx) = mpsc::channel::<Command>(1);
+        let (event_tx, event_rx) = mpsc::channel(1);
+        let mut target = self.clone();
+
+        tokio::spawn(async move {
+            while let Some(command) = command_rx.recv().await {
+                target.commands.lock().unwrap().push(command.clone());
+                for event in target.respond(command) {
+                    event_tx.send(event).await.unwrap();
+                }
+            }
+        });
+
+        (command_tx, event_rx)
+    }
+
+    fn respond(&mut self, command: Command) -> Vec<Event> {
+        match command {
+            Command::Read {
+                space,
+                addr,
+                nbytes,
+            } => {
+                let mut events = Vec::new();
+                // Offsets rather than addresses so that reads at the top of the address space do
+                // not overflow
+                for offset in (0..nbytes).step_by(4) {
+                    let word_addr = addr + u64::from(offset);
+                    if let Some((error_addr, count @ 1..)) = self.read_errors {
+                        if word_addr >= error_addr {
+                            self.read_errors = Some((error_addr, count - 1));
+                            events.push(Event::ReadError {
+                                addr: word_addr,
+                                nbytes: nbytes - offset,
+                            });
+                            break;
+                        }
+                    }
+                    let bytes = self.load(word_addr, (nbytes - offset).min(4));
+                    events.push(Event::Read {
+                        space: space.clone(),
+                        addr: word_addr,
+                        data: self.endianness.word_from_bytes(&bytes).into(),
+                    });
+                }
+                events
+            }
+            Command::Write {
+                space,
+                addr,
+                data,
+                width,
+            } => {
+                self.write(addr, data, width);
+                vec![Event::Write {
+                    space,
+                    addr,
+                    data,
+                    width,
+                }]
+            }
+            Command::WriteBlock { space, addr, data } => {
+                let len = data.len() as u32;
+                self.store(addr, &data);
+                vec![
+                    Event::Progress {
+                        done: len,
+                        total: len,
+                    },
+                    Event::WriteBlock { space, addr, len },
+                ]
+            }
+            Command::Fill {
+                addr,
+                len,
+                pattern,
+                width,
+                ..
+            } => {
+                let element = self
+                    .endianness
+                    .value_to_bytes(pattern, width.bytes() as usize);
+                let data = element
+                    .iter()
+                    .copied()
+                    .cycle()
+                    .take(len as usize)
+                    .collect::<Vec<_>>();
+                self.store(addr, &data);
+                vec![Event::Fill { addr, len }]
+            }
+            Command::Crc32 { addr, len, .. } => {
+                let crc = self
+                    .crc32
+                    .unwrap_or_else(|| panic!("unexpected command {command:?}"));
+                vec![Event::Crc32 { addr, len, crc }]
+            }
+            command => panic!("unexpected command {command:?}"),
+        }
+    }
+
+    fn store(&self, addr: u64, bytes: &[u8]) {
+        let mut memory = self.memory.lock().unwrap();
+        for (offset, byte) in (0..).zip(bytes) {
+            if Some(addr + offset) != self.stuck_addr {
+                memory.insert(addr + offset, *byte);
+            }
+        }
+    }
+
+    fn load(&self, addr: u64, nbytes: u32) -> Vec<u8> {
+        let memory = self.memory.lock().unwrap();
+        (0..u64::from(nbytes))
+            .map(|offset| {
+                let addr = addr + offset;
+                memory
+                    .get(&addr)
+                    .copied()
+                    .unwrap_or_else(|| (self.contents)(addr))
+            })
+            .collect()
+    }
+}
+
+/// Memory contents holding the low byte of each address.
+pub fn low_byte(addr: u64) -> u8 {
+    addr as u8
+}
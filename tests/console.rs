@@ -0,0 +1,35 @@
+use uart_dap::{decode_line, Statistics, StatisticsHandle};
+
+#[test]
+fn decodes_plain_line() {
+    let stats = StatisticsHandle::default();
+    assert_eq!(
+        decode_line(b"DEBUG> mr kernel 0x0\r", &stats),
+        "DEBUG> mr kernel 0x0"
+    );
+    assert_eq!(stats.get(), Statistics::default());
+}
+
+#[test]
+fn replaces_invalid_utf8() {
+    let stats = StatisticsHandle::default();
+    assert_eq!(decode_line(b"DEB\xffUG>", &stats), "DEB\u{fffd}UG>");
+    assert_eq!(stats.get().corrupted_bytes, 1);
+}
+
+#[test]
+fn strips_ansi_escapes() {
+    let stats = StatisticsHandle::default();
+    assert_eq!(
+        decode_line(b"\x1b[1;32mDEBUG>\x1b[0m \x1b]0;title\x07mr", &stats),
+        "DEBUG> mr"
+    );
+    assert_eq!(stats.get().discarded_bytes, 21);
+}
+
+#[test]
+fn applies_backspaces() {
+    let stats = StatisticsHandle::default();
+    assert_eq!(decode_line(b"mx\x08r kernel", &stats), "mr kernel");
+    assert_eq!(stats.get().discarded_bytes, 2);
+}
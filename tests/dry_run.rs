@@ -0,0 +1,185 @@
+mod common;
+
+use tokio::sync::mpsc;
+use uart_dap::{
+    AccessPolicy, AddressSpace, Command, Dialect, Event, LineEnding, UartDap, Violation, Width,
+};
+
+use common::{run, Transcript};
+
+#[tokio::test]
+async fn records_commands_without_sending() {
+    let transcript = Transcript::default();
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::CrLf)
+        .with_dialect(Dialect::uboot())
+        .with_policy(AccessPolicy::allow_all().with_denied(0x3000, 4));
+
+    let commands_and_events = vec![
+        (
+            Command::Read {
+                space: AddressSpace::kernel(),
+                addr: 0x1000,
+                nbytes: 6,
+            },
+            vec![
+                Event::Read {
+                    space: AddressSpace::kernel(),
+                    addr: 0x1000,
+                    data: 0,
+                },
+                Event::Read {
+                    space: AddressSpace::kernel(),
+                    addr: 0x1004,
+                    data: 0,
+                },
+            ],
+        ),
+        (
+            Command::set_bits(0x2000, 0x100),
+            vec![Event::Modify {
+                addr: 0x2000,
+                old: 0,
+                new: 0x100,
+            }],
+        ),
+        (
+            Command::Crc32 {
+                space: AddressSpace::kernel(),
+                addr: 0x1000,
+                len: 0x10,
+            },
+            vec![Event::Crc32 {
+                addr: 0x1000,
+                len: 0x10,
+                crc: 0,
+            }],
+        ),
+        (
+            Command::Write {
+                space: AddressSpace::kernel(),
+                addr: 0x3000,
+                data: 1,
+                width: Width::Word,
+            },
+            vec![Event::AccessDenied {
+                command: Command::Write {
+                    space: AddressSpace::kernel(),
+                    addr: 0x3000,
+                    data: 1,
+                    width: Width::Word,
+                },
+                violation: Violation::Denied { addr: 0x3000 },
+            }],
+        ),
+    ];
+    run(dap, commands_and_events).await;
+
+    assert_eq!(
+        transcript.text(),
+        "md.b 0x1000 0x6\r\nmd.b 0x2000 0x4\r\nmw.l 0x2000 0x100\r\ncrc32 0x1000 0x10\r\n"
+    );
+}
+
+#[tokio::test]
+async fn stops_search_at_top_of_address_space() {
+    let dap = UartDap::dry_run(std::io::sink(), LineEnding::Lf);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    let addr = u64::MAX - 3;
+    command_tx
+        .send(Command::Search {
+            space: AddressSpace::kernel(),
+            addr,
+            len: 3,
+            pattern: vec![0, 0],
+            mask: Vec::new(),
+            alignment: 2,
+            first_only: false,
+        })
+        .await
+        .unwrap();
+
+    let mut found = Vec::new();
+    loop {
+        match event_rx.recv().await.unwrap() {
+            Event::Found { addr } => found.push(addr),
+            Event::Search { matches, .. } => {
+                assert_eq!(matches, 1);
+                break;
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(found, [addr]);
+
+    drop(command_tx);
+    join_handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn fails_reads_past_top_of_address_space() {
+    let dap = UartDap::dry_run(std::io::sink(), LineEnding::Lf);
+
+    let commands_and_events = vec![(
+        Command::Read {
+            space: AddressSpace::kernel(),
+            addr: u64::MAX - 1,
+            nbytes: 4,
+        },
+        vec![Event::ReadError {
+            addr: u64::MAX - 1,
+            nbytes: 4,
+        }],
+    )];
+    run(dap, commands_and_events).await;
+}
+
+#[tokio::test]
+async fn carries_address_space_through_composite_commands() {
+    let transcript = Transcript::default();
+    let task = AddressSpace::new("task");
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::Lf)
+        .with_policy(AccessPolicy::allow_all().with_denied(0x1000, 0x10));
+
+    let commands_and_events = vec![
+        (
+            Command::Fill {
+                space: task.clone(),
+                addr: 0x1000,
+                len: 8,
+                pattern: 0x5a,
+                width: Width::Word,
+            },
+            vec![
+                Event::Progress { done: 4, total: 8 },
+                Event::Progress { done: 8, total: 8 },
+                Event::Fill {
+                    addr: 0x1000,
+                    len: 8,
+                },
+            ],
+        ),
+        (
+            Command::Modify {
+                space: task.clone(),
+                addr: 0x1004,
+                mask: 0xff00,
+                value: 0x1200,
+            },
+            vec![Event::Modify {
+                addr: 0x1004,
+                old: 0,
+                new: 0x1200,
+            }],
+        ),
+    ];
+    run(dap, commands_and_events).await;
+
+    assert_eq!(
+        transcript.text(),
+        "mw task 0x1000 0x5a\nmw task 0x1004 0x5a\nmr task 0x1004 4\nmw task 0x1004 0x1200\n"
+    );
+}
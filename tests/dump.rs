@@ -0,0 +1,68 @@
+mod common;
+
+use uart_dap::{Dump, Endianness, ImageFormat};
+
+use common::{low_byte, FakeTarget};
+
+// Holds the low byte of each address. The first read reaching `fail_at` stops with a read error
+// there.
+fn fake_target(fail_at: u64) -> FakeTarget {
+    FakeTarget::new(Endianness::Little)
+        .with_contents(low_byte)
+        .with_read_errors(fail_at, 1)
+}
+
+#[tokio::test]
+async fn reads_aligned_chunks() {
+    let target = fake_target(u64::MAX);
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+    let mut dump = Dump::new(0x1008, 0x1a).with_chunk_size(0x10);
+
+    let mut progress = Vec::new();
+    dump.read(&mut dap, |done, total| progress.push((done, total)))
+        .await
+        .unwrap();
+
+    assert!(dump.is_complete());
+    assert_eq!(progress, [(0x08, 0x1a), (0x18, 0x1a), (0x1a, 0x1a)]);
+    assert_eq!(dump.data(), (0x08..0x22).collect::<Vec<u8>>());
+}
+
+#[tokio::test]
+async fn resumes_after_read_error() {
+    let target = fake_target(0x1010);
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+    let mut dump = Dump::new(0x1000, 0x20).with_chunk_size(0x20);
+
+    assert!(dump.read(&mut dap, |_, _| {}).await.is_err());
+    assert!(!dump.is_complete());
+    assert_eq!(dump.next_addr(), Some(0x1010));
+
+    let mut partial = Vec::new();
+    dump.write(&mut partial, ImageFormat::Binary).unwrap();
+    assert_eq!(partial, (0x00..0x10).collect::<Vec<u8>>());
+
+    dump.read(&mut dap, |_, _| {}).await.unwrap();
+    assert!(dump.is_complete());
+    assert_eq!(dump.data(), (0x00..0x20).collect::<Vec<u8>>());
+}
+
+#[tokio::test]
+async fn reads_to_the_top_of_the_address_space() {
+    let target = fake_target(u64::MAX);
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+    let mut dump = Dump::new(0xffff_ffff_ffff_ff00, 0x100).with_chunk_size(0x40);
+
+    let mut progress = Vec::new();
+    dump.read(&mut dap, |done, _| progress.push(done))
+        .await
+        .unwrap();
+
+    assert!(dump.is_complete());
+    assert_eq!(dump.next_addr(), None);
+    assert_eq!(progress, [0x40, 0x80, 0xc0, 0x100]);
+    assert_eq!(dump.data(), (0x00..=0xff).collect::<Vec<u8>>());
+}
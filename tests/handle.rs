@@ -0,0 +1,171 @@
+mod common;
+
+use tokio::sync::mpsc;
+use uart_dap::{
+    AddressSpace, Alignment, Command, DapHandle, Dialect, Endianness, Event, LineEnding, UartDap,
+    Width,
+};
+
+use common::{FakeTarget, Transcript};
+
+const ENDIANNESS: Endianness = Endianness::Little;
+
+fn handle<'a>(
+    dialect: Dialect,
+    command_tx: &'a mpsc::Sender<Command>,
+    event_rx: &'a mut mpsc::Receiver<Event>,
+) -> DapHandle<'a> {
+    UartDap::dry_run(std::io::sink(), LineEnding::Lf)
+        .with_dialect(dialect)
+        .with_endianness(ENDIANNESS)
+        .handle(command_tx, event_rx)
+}
+
+#[tokio::test]
+async fn writes_each_element_at_its_width() {
+    let target = FakeTarget::new(ENDIANNESS);
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = handle(Dialect::uboot(), &command_tx, &mut event_rx);
+
+    dap.write::<i16>(0x1002, -2).await.unwrap();
+    dap.write::<[u8; 2]>(0x1004, [0x12, 0x34]).await.unwrap();
+
+    assert_eq!(
+        target.commands(),
+        vec![
+            Command::Fill {
+                space: AddressSpace::kernel(),
+                addr: 0x1002,
+                len: 2,
+                pattern: 0xfffe,
+                width: Width::Halfword,
+            },
+            Command::Fill {
+                space: AddressSpace::kernel(),
+                addr: 0x1004,
+                len: 1,
+                pattern: 0x12,
+                width: Width::Byte,
+            },
+            Command::Fill {
+                space: AddressSpace::kernel(),
+                addr: 0x1005,
+                len: 1,
+                pattern: 0x34,
+                width: Width::Byte,
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn reads_back_typed_values() {
+    let target = FakeTarget::new(ENDIANNESS);
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = handle(Dialect::uboot(), &command_tx, &mut event_rx);
+
+    dap.write::<f32>(0x2000, 1.5).await.unwrap();
+    dap.write::<[i16; 3]>(0x2006, [-1, 2, -3]).await.unwrap();
+    dap.write::<u64>(0x2010, 0x0123_4567_89ab_cdef)
+        .await
+        .unwrap();
+
+    assert_eq!(dap.read::<f32>(0x2000).await.unwrap(), 1.5);
+    assert_eq!(
+        dap.read_array::<i16>(0x2006, 3).await.unwrap(),
+        vec![-1, 2, -3]
+    );
+    assert_eq!(dap.read::<[i16; 3]>(0x2006).await.unwrap(), [-1, 2, -3]);
+    assert_eq!(
+        dap.read::<u64>(0x2010).await.unwrap(),
+        0x0123_4567_89ab_cdef
+    );
+
+    // Reads ask for exactly the bytes of the value
+    let reads = target
+        .commands()
+        .iter()
+        .filter_map(|command| match command {
+            Command::Read { addr, nbytes, .. } => Some((*addr, *nbytes)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reads,
+        vec![(0x2000, 4), (0x2006, 6), (0x2006, 6), (0x2010, 8)]
+    );
+}
+
+#[tokio::test]
+async fn writes_words_without_narrow_writes() {
+    let target = FakeTarget::new(ENDIANNESS);
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = handle(Dialect::integrity(), &command_tx, &mut event_rx);
+
+    dap.write::<i32>(0x3000, -2).await.unwrap();
+    dap.write::<u64>(0x3008, 0x0123_4567_89ab_cdef)
+        .await
+        .unwrap();
+    assert_eq!(
+        dap.read::<u64>(0x3008).await.unwrap(),
+        0x0123_4567_89ab_cdef
+    );
+
+    // Narrow writes would rewrite the word around them
+    assert!(dap.write::<[u16; 2]>(0x3010, [1, 2]).await.is_err());
+    assert!(dap.write::<u8>(0x3014, 1).await.is_err());
+
+    let writes = target
+        .commands()
+        .iter()
+        .filter(|command| !matches!(command, Command::Read { .. }))
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        writes,
+        vec![
+            Command::Write {
+                space: AddressSpace::kernel(),
+                addr: 0x3000,
+                data: 0xffff_fffe,
+                width: Width::Word,
+            },
+            Command::Write {
+                space: AddressSpace::kernel(),
+                addr: 0x3008,
+                data: 0x89ab_cdef,
+                width: Width::Word,
+            },
+            Command::Write {
+                space: AddressSpace::kernel(),
+                addr: 0x300c,
+                data: 0x0123_4567,
+                width: Width::Word,
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn merges_narrow_writes_when_splitting() {
+    let transcript = Transcript::default();
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::Lf)
+        .with_dialect(Dialect::integrity())
+        .with_endianness(ENDIANNESS)
+        .with_alignment(Alignment::Split);
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+    let mut handle = dap.handle(&command_tx, &mut event_rx);
+
+    tokio::select! {
+        result = handle.write::<u16>(0x3012, 0xbeef) => result,
+        result = dap.run(command_rx, event_tx) => result,
+    }
+    .unwrap();
+
+    // The dry run reads the word around the halfword as zero
+    assert_eq!(
+        transcript.text(),
+        "mr kernel 0x3010 4\nmw kernel 0x3010 0xbeef0000\n"
+    );
+}
@@ -0,0 +1,99 @@
+use uart_dap::{Endianness, HexdumpError, HexdumpLine, HexdumpParser};
+
+#[test]
+fn parses_byte_groups_with_ascii_column() {
+    let parser = HexdumpParser::default();
+    assert_eq!(
+        parser.parse_line("[20220204T044316] c0000010: 03 0a 30 18  00 00 00 00 |..0.....|"),
+        Ok(HexdumpLine {
+            addr: 0xc0000010,
+            bytes: vec![0x03, 0x0a, 0x30, 0x18, 0, 0, 0, 0],
+            ascii: Some("..0.....".to_string()),
+        })
+    );
+}
+
+#[test]
+fn parses_word_groups_by_endianness() {
+    let line = "0x80000000:  12345678 9abcdef0";
+    assert_eq!(
+        HexdumpParser::new(Endianness::Big)
+            .parse_line(line)
+            .unwrap()
+            .bytes,
+        [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0]
+    );
+    assert_eq!(
+        HexdumpParser::new(Endianness::Little)
+            .parse_line(line)
+            .unwrap()
+            .bytes,
+        [0x78, 0x56, 0x34, 0x12, 0xf0, 0xde, 0xbc, 0x9a]
+    );
+}
+
+#[test]
+fn parses_halfword_groups_with_undelimited_ascii_column() {
+    let parsed = HexdumpParser::default()
+        .parse_expected("80000000: 4142 4344    ABCD", 0x80000000, 4)
+        .unwrap();
+    assert_eq!(parsed.bytes, b"ABCD");
+    assert_eq!(parsed.ascii.as_deref(), Some("ABCD"));
+}
+
+#[test]
+fn consumes_at_most_remaining_bytes() {
+    let parsed = HexdumpParser::default()
+        .parse_expected("1000: 01 02 03 04 05 06 07 08", 0x1000, 3)
+        .unwrap();
+    assert_eq!(parsed.bytes, [1, 2, 3]);
+}
+
+#[test]
+fn skips_data_beyond_remaining_bytes_before_undelimited_ascii_column() {
+    let parsed = HexdumpParser::default()
+        .parse_expected("1000: 41 42 43 44    ABCD", 0x1000, 2)
+        .unwrap();
+    assert_eq!(parsed.bytes, b"AB");
+    assert_eq!(parsed.ascii.as_deref(), Some("ABCD"));
+}
+
+#[test]
+fn rejects_unexpected_address() {
+    assert_eq!(
+        HexdumpParser::default().parse_expected("1010: 01 02 03 04", 0x1000, 16),
+        Err(HexdumpError::AddressMismatch {
+            expected: 0x1000,
+            actual: 0x1010
+        })
+    );
+}
+
+#[test]
+fn rejects_other_console_output() {
+    let parser = HexdumpParser::default();
+    assert_eq!(
+        parser.parse_line("DEBUG> mr kernel 0x1000"),
+        Err(HexdumpError::NotHexdump)
+    );
+    assert_eq!(
+        parser.parse_line("[12:03:44] boot: ok"),
+        Err(HexdumpError::NotHexdump)
+    );
+}
+
+#[test]
+fn rejects_ascii_column_mismatch() {
+    let parser = HexdumpParser::default();
+    assert!(parser
+        .parse_expected("1000: 41 42 00 20 |AB. |", 0x1000, 4)
+        .is_ok());
+    assert_eq!(
+        parser.parse_expected("1000: 41 42 00 20 |AC. |", 0x1000, 4),
+        Err(HexdumpError::AsciiMismatch)
+    );
+    assert_eq!(
+        parser.parse_expected("1000: 41 42 00 20 |AB|", 0x1000, 4),
+        Err(HexdumpError::AsciiMismatch)
+    );
+}
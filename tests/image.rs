@@ -0,0 +1,199 @@
+use uart_dap::image::{read_image, write_image};
+use uart_dap::{ImageFormat, Segment};
+
+fn write(format: ImageFormat, segments: &[Segment]) -> Vec<u8> {
+    let mut output = Vec::new();
+    write_image(&mut output, format, segments).unwrap();
+    output
+}
+
+#[test]
+fn writes_binary_with_filled_gaps() {
+    let segments = [
+        Segment::new(0x1004, vec![3, 4]),
+        Segment::new(0x1000, vec![1, 2]),
+    ];
+    assert_eq!(
+        write(ImageFormat::Binary, &segments),
+        [1, 2, 0xff, 0xff, 3, 4]
+    );
+}
+
+#[test]
+fn writes_intel_hex_with_extended_addresses() {
+    let segments = [
+        Segment::new(0x0100, vec![1, 2, 3, 4]),
+        Segment::new(0x0001_fffe, vec![5, 6, 7, 8]),
+    ];
+    let output = String::from_utf8(write(ImageFormat::IntelHex, &segments)).unwrap();
+    assert_eq!(
+        output.lines().collect::<Vec<_>>(),
+        [
+            ":0401000001020304F1",
+            ":020000040001F9",
+            ":02FFFE000506F6",
+            ":020000040002F8",
+            ":020000000708EF",
+            ":00000001FF",
+        ]
+    );
+}
+
+#[test]
+fn writes_srecord() {
+    let segments = [Segment::new(0x0001_0000, vec![1, 2, 3, 4])];
+    let output = String::from_utf8(write(ImageFormat::SRecord, &segments)).unwrap();
+    assert_eq!(
+        output.lines().collect::<Vec<_>>(),
+        [
+            "S00B0000756172742D646170D6",
+            "S3090001000001020304EB",
+            "S70500000000FA",
+        ]
+    );
+}
+
+#[test]
+fn writes_records_up_to_4_gib() {
+    let segments = [Segment::new(0xffff_fff0, vec![0; 16])];
+    let output = String::from_utf8(write(ImageFormat::IntelHex, &segments)).unwrap();
+    assert_eq!(
+        output.lines().nth(1),
+        Some(":10FFF0000000000000000000000000000000000001")
+    );
+    let output = String::from_utf8(write(ImageFormat::SRecord, &segments)).unwrap();
+    assert_eq!(
+        output.lines().nth(1),
+        Some("S315FFFFFFF000000000000000000000000000000000FD")
+    );
+
+    // The second half of the record would start at 4 GiB
+    let segments = [Segment::new(0xffff_fff8, vec![0; 16])];
+    let mut output = Vec::new();
+    let error = write_image(&mut output, ImageFormat::IntelHex, &segments).unwrap_err();
+    assert_eq!(error.to_string(), "Intel HEX cannot address 0x100000000");
+
+    let segments = [Segment::new(0x1_0000_0000, vec![0; 4])];
+    let error = write_image(&mut output, ImageFormat::SRecord, &segments).unwrap_err();
+    assert_eq!(error.to_string(), "S-record cannot address 0x100000000");
+}
+
+#[test]
+fn writes_hexdump_at_the_top_of_the_address_space() {
+    let segments = [Segment::new(
+        0xffff_ffff_ffff_fff0,
+        b"0123456789abcdef".to_vec(),
+    )];
+    let output = String::from_utf8(write(ImageFormat::Hexdump, &segments)).unwrap();
+    assert_eq!(
+        output.lines().collect::<Vec<_>>(),
+        [
+            "fffffffffffffff0  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|",
+            "10000000000000000",
+        ]
+    );
+}
+
+#[test]
+fn writes_canonical_hexdump() {
+    let segments = [Segment::new(0x1000, b"Hello, world!\n\0\x7fXYZ".to_vec())];
+    let output = String::from_utf8(write(ImageFormat::Hexdump, &segments)).unwrap();
+    assert_eq!(
+        output,
+        "\
+00001000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 7f  |Hello, world!...|
+00001010  58 59 5a                                          |XYZ|
+00001013
+"
+    );
+}
+
+#[test]
+fn reads_back_written_images() {
+    let segments = vec![
+        Segment::new(0x0001_fff8, (0..40).collect()),
+        Segment::new(0x0003_0000, vec![0xaa; 3]),
+    ];
+    for format in [ImageFormat::IntelHex, ImageFormat::SRecord] {
+        let image = write(format, &segments);
+        assert_eq!(ImageFormat::detect(&image), format);
+        assert_eq!(read_image(&image, format, 0).unwrap(), segments, "{format}");
+    }
+}
+
+#[test]
+fn reads_binary_at_base_address() {
+    let image = [1, 2, 3];
+    assert_eq!(ImageFormat::detect(&image), ImageFormat::Binary);
+    assert_eq!(
+        read_image(&image, ImageFormat::Binary, 0x8000).unwrap(),
+        [Segment::new(0x8000, vec![1, 2, 3])]
+    );
+}
+
+#[test]
+fn rejects_bad_checksum() {
+    let image = b":0401000001020304F2\n:00000001FF\n";
+    let error = read_image(image, ImageFormat::IntelHex, 0).unwrap_err();
+    assert_eq!(error.to_string(), "line 1: bad checksum");
+}
+
+// A 32-bit little-endian ELF file with a PT_LOAD segment holding 4 bytes of data followed by 4
+// bytes of .bss, and a PT_NOTE segment that is not loaded
+fn elf32() -> Vec<u8> {
+    let mut elf = vec![0u8; 0x34];
+    elf[..6].copy_from_slice(b"\x7fELF\x01\x01");
+    elf[0x1c..0x20].copy_from_slice(&0x34u32.to_le_bytes());
+    elf[0x2a..0x2c].copy_from_slice(&0x20u16.to_le_bytes());
+    elf[0x2c..0x2e].copy_from_slice(&2u16.to_le_bytes());
+
+    let data_offset = 0x34 + 2 * 0x20;
+    for (p_type, p_paddr) in [(1u32, 0x2000_0000u32), (4, 0)] {
+        // p_type, p_offset, p_vaddr, p_paddr, p_filesz, p_memsz, p_flags, p_align
+        for field in [p_type, data_offset, 0x8000_0000, p_paddr, 4, 8, 0, 4] {
+            elf.extend(field.to_le_bytes());
+        }
+    }
+    elf.extend([0xde, 0xad, 0xbe, 0xef]);
+    elf
+}
+
+#[test]
+fn reads_elf_load_segments() {
+    let elf = elf32();
+    assert_eq!(ImageFormat::detect(&elf), ImageFormat::Elf);
+    assert_eq!(
+        read_image(&elf, ImageFormat::Elf, 0).unwrap(),
+        [Segment::new(
+            0x2000_0000,
+            vec![0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 0]
+        )]
+    );
+}
+
+// A 64-bit little-endian ELF file with a PT_LOAD segment of 4 bytes above 4 GiB
+fn elf64() -> Vec<u8> {
+    let mut elf = vec![0u8; 0x40];
+    elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+    elf[0x20..0x28].copy_from_slice(&0x40u64.to_le_bytes());
+    elf[0x36..0x38].copy_from_slice(&0x38u16.to_le_bytes());
+    elf[0x38..0x3a].copy_from_slice(&1u16.to_le_bytes());
+
+    // p_type, p_flags
+    elf.extend(1u32.to_le_bytes());
+    elf.extend(0u32.to_le_bytes());
+    // p_offset, p_vaddr, p_paddr, p_filesz, p_memsz, p_align
+    for field in [0x40 + 0x38, 0x1_2000_0000, 0x1_2000_0000, 4, 4, 4u64] {
+        elf.extend(field.to_le_bytes());
+    }
+    elf.extend([0xde, 0xad, 0xbe, 0xef]);
+    elf
+}
+
+#[test]
+fn reads_elf64_segments_above_4_gib() {
+    assert_eq!(
+        read_image(&elf64(), ImageFormat::Elf, 0).unwrap(),
+        [Segment::new(0x1_2000_0000, vec![0xde, 0xad, 0xbe, 0xef])]
+    );
+}
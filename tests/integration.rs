@@ -1,13 +1,15 @@
 // Based on: https://github.com/berkowski/tokio-serial/blob/master/tests/test_serialstream.rs
 
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::{process, sync::mpsc, time};
 use tokio_serial::SerialPortBuilderExt;
 use tracing::{info, trace};
-use tracing_subscriber;
 
-use uart_dap::{Command, Echo, Event, LineEnding, UartDap};
+use uart_dap::{
+    AccessPolicy, AddressSpace, BlockWrite, Command, Credentials, Dialect, Echo, Endianness, Event,
+    LineEnding, UartDap, Violation, Width,
+};
 
 #[cfg(unix)]
 const DEFAULT_TEST_PORT_NAMES: &str = concat!(
@@ -80,6 +82,13 @@ async fn setup_virtual_serial_ports() -> Fixture {
     Fixture::new(port_names[0], port_names[1]).await
 }
 
+// Reads exactly the text of `expected` from the model side of the port and checks it
+async fn expect_serial(model_rx: &mut (impl AsyncReadExt + Unpin), expected: &str) {
+    let mut buf = vec![0u8; expected.len()];
+    model_rx.read_exact(&mut buf).await.unwrap();
+    assert_eq!(std::str::from_utf8(&buf).unwrap(), expected);
+}
+
 #[tokio::test]
 async fn performs_write_command() {
     let _ = tracing_subscriber::fmt::try_init();
@@ -102,8 +111,10 @@ async fn performs_write_command() {
     time::sleep(Duration::from_millis(500)).await;
 
     let command = Command::Write {
+        space: AddressSpace::kernel(),
         addr: 0x600df00d,
         data: 0xa5a5a5a5,
+        width: Width::Word,
     };
     info!("Sending command");
     command_tx.send(command).await.unwrap();
@@ -112,8 +123,10 @@ async fn performs_write_command() {
     assert_eq!(
         event_rx.recv().await.unwrap(),
         Event::Write {
+            space: AddressSpace::kernel(),
             addr: 0x600df00d,
-            data: 0xa5a5a5a5
+            data: 0xa5a5a5a5,
+            width: Width::Word,
         }
     );
     let mut buf = [0u8; 32];
@@ -132,12 +145,14 @@ async fn performs_write_command() {
 }
 
 #[tokio::test]
-async fn performs_read_command() {
+async fn refuses_writes_outside_policy() {
     let _ = tracing_subscriber::fmt::try_init();
 
     let fixture = setup_virtual_serial_ports().await;
 
-    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf).unwrap();
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_policy(AccessPolicy::allow_all().with_read_only(0x1000, 0x100));
     let model = tokio_serial::new(fixture.port_b, 115200)
         .open_native_async()
         .unwrap();
@@ -152,63 +167,345 @@ async fn performs_read_command() {
     model_tx.write_all(b"DEBUG> ").await.unwrap();
     time::sleep(Duration::from_millis(500)).await;
 
-    let command = Command::Read {
-        addr: 0x600df00d,
-        nbytes: 20,
+    let command = Command::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x1004,
+        data: 0xa5,
+        width: Width::Word,
+    };
+    info!("Sending refused command");
+    command_tx.send(command.clone()).await.unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::AccessDenied {
+            command,
+            violation: Violation::ReadOnly { addr: 0x1004 }
+        }
+    );
+
+    info!("Sending allowed command");
+    command_tx
+        .send(Command::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x2000,
+            data: 0xa5,
+            width: Width::Word,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x2000,
+            data: 0xa5,
+            width: Width::Word,
+        }
+    );
+    let mut buf = [0u8; 32];
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mw kernel 0x2000 0xa5\n"
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn performs_write_block_command() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dialect = Dialect {
+        block_write: BlockWrite::MultiValue { max_values: 4 },
+        ..Dialect::integrity()
+    };
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_dialect(dialect);
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::WriteBlock {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        data: vec![0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0],
     };
     info!("Sending command");
     command_tx.send(command).await.unwrap();
 
-    let mut buf = [0u8; 32];
+    info!("Awaiting events");
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Progress { done: 8, total: 8 }
+    );
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::WriteBlock {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            len: 8
+        }
+    );
+    let mut buf = [0u8; 64];
     info!("Awaiting serial");
     let n = model_rx.read(&mut buf).await.unwrap();
     assert_eq!(
         std::str::from_utf8(&buf[..n]).unwrap(),
-        "mr kernel 0x600df00d 20\n"
+        "mw kernel 0x1000 0x12345678 0x9abcdef0\n"
     );
 
-    model_tx
-        .write_all(b"600df00d: 5a 5a 5a 5a  01 02 03 04  05 06 07 08  09 0a 0b 0c |-------|\n")
-        .await
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn performs_one_word_write_block_command() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dialect = Dialect {
+        block_write: BlockWrite::MultiValue { max_values: 4 },
+        ..Dialect::integrity()
+    };
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_dialect(dialect);
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
         .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::WriteBlock {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        data: vec![0x12, 0x34, 0x56, 0x78],
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
     info!("Awaiting events");
     assert_eq!(
         event_rx.recv().await.unwrap(),
-        Event::Read {
-            addr: 0x600df00d,
-            data: 0x5a5a5a5a,
-        }
+        Event::Progress { done: 4, total: 4 }
     );
     assert_eq!(
         event_rx.recv().await.unwrap(),
-        Event::Read {
-            addr: 0x600df011,
-            data: 0x04030201,
+        Event::WriteBlock {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            len: 4
         }
     );
+    let mut buf = [0u8; 64];
+    info!("Awaiting serial");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mw kernel 0x1000 0x12345678\n"
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn fills_natively_on_uboot() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_dialect(Dialect::uboot());
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"=> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::Fill {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        len: 16,
+        pattern: 0xa5,
+        width: Width::Byte,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    info!("Awaiting event");
     assert_eq!(
         event_rx.recv().await.unwrap(),
-        Event::Read {
-            addr: 0x600df015,
-            data: 0x08070605,
+        Event::Fill {
+            addr: 0x1000,
+            len: 16
         }
     );
+    let mut buf = [0u8; 32];
+    info!("Awaiting serial");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mw.b 0x1000 0xa5 0x10\n"
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn ignores_address_spaces_on_uboot() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_dialect(Dialect::uboot());
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"=> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::Write {
+        space: AddressSpace::new("task1"),
+        addr: 0x1000,
+        data: 0x1,
+        width: Width::Word,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    info!("Awaiting event");
     assert_eq!(
         event_rx.recv().await.unwrap(),
-        Event::Read {
-            addr: 0x600df019,
-            data: 0x0c0b0a09,
+        Event::Write {
+            space: AddressSpace::new("task1"),
+            addr: 0x1000,
+            data: 0x1,
+            width: Width::Word,
         }
     );
+    let mut buf = [0u8; 32];
+    info!("Awaiting serial");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(std::str::from_utf8(&buf[..n]).unwrap(), "mw.l 0x1000 0x1\n");
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn computes_crc32_natively_on_uboot() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_dialect(Dialect::uboot());
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"=> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    info!("Sending command");
+    command_tx
+        .send(Command::Crc32 {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            len: 16,
+        })
+        .await
+        .unwrap();
+
+    let mut buf = [0u8; 32];
+    info!("Awaiting serial");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "crc32 0x1000 0x10\n"
+    );
+
+    info!("Sending serial response");
     model_tx
-        .write_all(b"600df01d: 0d 0e 0f 10                                        |-------|\n")
+        .write_all(b"crc32 for 00001000 ... 0000100f ==> 4e79f1d3\n=> ")
         .await
         .unwrap();
+
+    info!("Awaiting event");
     assert_eq!(
         event_rx.recv().await.unwrap(),
-        Event::Read {
-            addr: 0x600df01d,
-            data: 0x100f0e0d
+        Event::Crc32 {
+            addr: 0x1000,
+            len: 16,
+            crc: 0x4e79_f1d3
         }
     );
 
@@ -218,3 +515,1247 @@ async fn performs_read_command() {
         join_handle.abort();
     }
 }
+
+#[tokio::test]
+async fn copies_through_host() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf).unwrap();
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::Copy {
+        space: AddressSpace::kernel(),
+        src: 0x1000,
+        dst: 0x2000,
+        len: 6,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    info!("Awaiting source read");
+    expect_serial(&mut model_rx, "mr kernel 0x1000 6\n").await;
+    model_tx
+        .write_all(b"1000: 01 02 03 04  05 06 |......|\nDEBUG> ")
+        .await
+        .unwrap();
+
+    info!("Awaiting destination writes");
+    expect_serial(&mut model_rx, "mw kernel 0x2000 0x1020304\n").await;
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Progress { done: 4, total: 6 }
+    );
+    expect_serial(&mut model_rx, "mr kernel 0x2004 4\n").await;
+    model_tx
+        .write_all(b"2004: 00 00 00 00 |....|\nDEBUG> ")
+        .await
+        .unwrap();
+    expect_serial(&mut model_rx, "mw kernel 0x2004 0x5060000\n").await;
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Progress { done: 6, total: 6 }
+    );
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Copy {
+            src: 0x1000,
+            dst: 0x2000,
+            len: 6
+        }
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn searches_across_reads() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf).unwrap();
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    // Matches at 0x1002, at 0x1010 where only an uncompared bit differs, and across the two reads
+    // at 0x10fe. The one at 0x1007 is not aligned.
+    let mut memory = vec![0u8; 0x104];
+    for (offset, bytes) in [
+        (0x02, [0xca, 0xfe, 0x42]),
+        (0x07, [0xca, 0xfe, 0x42]),
+        (0x10, [0xca, 0xfe, 0x43]),
+        (0xfe, [0xca, 0xfe, 0x42]),
+    ] {
+        memory[offset..offset + 3].copy_from_slice(&bytes);
+    }
+    fn hexdump(addr: u32, bytes: &[u8]) -> String {
+        let mut text = String::new();
+        for (idx, line) in bytes.chunks(16).enumerate() {
+            text += &format!("{:x}:", addr + idx as u32 * 16);
+            for byte in line {
+                text += &format!(" {byte:02x}");
+            }
+            let ascii = line
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                .collect::<String>();
+            text += &format!(" |{ascii}|\n");
+        }
+        text + "DEBUG> "
+    }
+
+    let command = Command::Search {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        len: 0x104,
+        pattern: vec![0xca, 0xfe, 0x42],
+        mask: vec![0xff, 0xff, 0xfe],
+        alignment: 2,
+        first_only: false,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    info!("Awaiting first read");
+    expect_serial(&mut model_rx, "mr kernel 0x1000 256\n").await;
+    model_tx
+        .write_all(hexdump(0x1000, &memory[..0x100]).as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Progress {
+            done: 0x100,
+            total: 0x104
+        }
+    );
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Found { addr: 0x1002 }
+    );
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Found { addr: 0x1010 }
+    );
+
+    info!("Awaiting second read");
+    expect_serial(&mut model_rx, "mr kernel 0x1100 4\n").await;
+    model_tx
+        .write_all(hexdump(0x1100, &memory[0x100..]).as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Progress {
+            done: 0x104,
+            total: 0x104
+        }
+    );
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Found { addr: 0x10fe }
+    );
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Search {
+            addr: 0x1000,
+            len: 0x104,
+            matches: 3
+        }
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn sets_bits_with_read_modify_write() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf).unwrap();
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    info!("Sending command");
+    command_tx
+        .send(Command::set_bits(0x1000, 0x100))
+        .await
+        .unwrap();
+
+    info!("Awaiting read");
+    let mut buf = [0u8; 32];
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mr kernel 0x1000 4\n"
+    );
+    model_tx
+        .write_all(b"1000: 00 00 00 01 |....|\nDEBUG> ")
+        .await
+        .unwrap();
+
+    info!("Awaiting write");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mw kernel 0x1000 0x101\n"
+    );
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+
+    info!("Awaiting event");
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Modify {
+            addr: 0x1000,
+            old: 0x1,
+            new: 0x101
+        }
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn undoes_journaled_writes() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let journal = concat!(env!("CARGO_TARGET_TMPDIR"), "/journal.txt");
+    std::fs::remove_file(journal).ok();
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_journal(journal);
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    info!("Sending write");
+    command_tx
+        .send(Command::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            data: 0xa5,
+            width: Width::Word,
+        })
+        .await
+        .unwrap();
+    expect_serial(&mut model_rx, "mr kernel 0x1000 4\n").await;
+    model_tx
+        .write_all(b"1000: 00 00 00 01 |....|\nDEBUG> ")
+        .await
+        .unwrap();
+    expect_serial(&mut model_rx, "mw kernel 0x1000 0xa5\n").await;
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            data: 0xa5,
+            width: Width::Word,
+        }
+    );
+
+    info!("Sending undo");
+    command_tx.send(Command::undo_all()).await.unwrap();
+    expect_serial(&mut model_rx, "mw kernel 0x1000 0x1\n").await;
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    assert_eq!(event_rx.recv().await.unwrap(), Event::Undo { count: 1 });
+
+    let lines = std::fs::read_to_string(journal).unwrap();
+    let lines = lines
+        .lines()
+        .map(|line| {
+            let fields = line.split(' ').collect::<Vec<_>>();
+            [fields[0], fields[2], fields[3], fields[4], fields[5]].join(" ")
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "write kernel 0x00001000 0x00000001 0x000000a5",
+            "undo kernel 0x00001000 0x000000a5 0x00000001"
+        ]
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn keeps_journal_entry_when_undo_fails() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let journal = concat!(env!("CARGO_TARGET_TMPDIR"), "/failed_undo_journal.txt");
+    std::fs::remove_file(journal).ok();
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_journal(journal)
+        .with_response_timeout(Duration::from_millis(200));
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    info!("Sending write whose prompt is dropped");
+    command_tx
+        .send(Command::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            data: 0xa5,
+            width: Width::Word,
+        })
+        .await
+        .unwrap();
+    expect_serial(&mut model_rx, "mr kernel 0x1000 4\n").await;
+    model_tx
+        .write_all(b"1000: 00 00 00 01 |....|\nDEBUG> ")
+        .await
+        .unwrap();
+    expect_serial(&mut model_rx, "mw kernel 0x1000 0xa5\n").await;
+    assert!(matches!(
+        event_rx.recv().await.unwrap(),
+        Event::Write { addr: 0x1000, .. }
+    ));
+
+    info!("Sending undo refused for the lost prompt");
+    command_tx.send(Command::undo_all()).await.unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::PromptTimeout {
+            command: Command::Write {
+                space: AddressSpace::kernel(),
+                addr: 0x1000,
+                data: 0x1,
+                width: Width::Word,
+            }
+        }
+    );
+    expect_serial(&mut model_rx, "\n").await;
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+
+    info!("Sending undo again");
+    command_tx.send(Command::undo_all()).await.unwrap();
+    expect_serial(&mut model_rx, "mw kernel 0x1000 0x1\n").await;
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    assert_eq!(event_rx.recv().await.unwrap(), Event::Undo { count: 1 });
+
+    let lines = std::fs::read_to_string(journal).unwrap();
+    let kinds = lines
+        .lines()
+        .map(|line| line.split(' ').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, ["write", "undo"]);
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn waits_for_condition() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf).unwrap();
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let wait_for = |timeout| Command::WaitFor {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        mask: 0x1,
+        expected: 0x1,
+        interval: Duration::from_millis(10),
+        timeout,
+    };
+    let mut buf = [0u8; 32];
+
+    info!("Sending command that times out");
+    command_tx.send(wait_for(Duration::ZERO)).await.unwrap();
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mr kernel 0x1000 4\n"
+    );
+    model_tx
+        .write_all(b"1000: 00 00 00 02 |....|\nDEBUG> ")
+        .await
+        .unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::WaitTimeout {
+            addr: 0x1000,
+            value: 0x2
+        }
+    );
+
+    info!("Sending command that completes on the second poll");
+    command_tx
+        .send(wait_for(Duration::from_secs(5)))
+        .await
+        .unwrap();
+    for response in [&b"00 00 00 00"[..], &b"00 00 00 03"[..]] {
+        let n = model_rx.read(&mut buf).await.unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf[..n]).unwrap(),
+            "mr kernel 0x1000 4\n"
+        );
+        model_tx.write_all(b"1000: ").await.unwrap();
+        model_tx.write_all(response).await.unwrap();
+        model_tx.write_all(b" |....|\nDEBUG> ").await.unwrap();
+    }
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::WaitFor {
+            addr: 0x1000,
+            value: 0x3
+        }
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn reports_watched_changes() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf).unwrap();
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::Watch {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        nbytes: 8,
+        period: Duration::from_millis(50),
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    let before = SystemTime::now();
+    let mut buf = [0u8; 32];
+    for (poll, response) in [
+        &b"1000: 00 00 00 00  00 00 00 07 |........|"[..],
+        &b"1000: 00 00 00 00  00 00 00 07 |........|"[..],
+        &b"1000: 00 00 00 00  00 00 00 08 |........|"[..],
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        info!("Awaiting poll");
+        let n = model_rx.read(&mut buf).await.unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf[..n]).unwrap(),
+            "mr kernel 0x1000 8\n"
+        );
+        model_tx.write_all(response).await.unwrap();
+        model_tx.write_all(b"\nDEBUG> ").await.unwrap();
+
+        // The first poll reports every word
+        if poll == 0 {
+            for (addr, value) in [(0x1000, 0x0), (0x1004, 0x7)] {
+                match event_rx.recv().await.unwrap() {
+                    Event::Watched {
+                        space,
+                        addr: watched_addr,
+                        value: watched_value,
+                        timestamp,
+                    } => {
+                        assert_eq!(space, AddressSpace::kernel());
+                        assert_eq!((watched_addr, watched_value), (addr, value));
+                        assert!(timestamp > before);
+                    }
+                    event => panic!("unexpected event {event:?}"),
+                }
+            }
+        }
+    }
+
+    info!("Awaiting event");
+    match event_rx.recv().await.unwrap() {
+        Event::Changed {
+            space,
+            addr,
+            old,
+            new,
+            timestamp,
+        } => {
+            assert_eq!(space, AddressSpace::kernel());
+            assert_eq!((addr, old, new), (0x1004, 0x7, 0x8));
+            assert!(timestamp > before);
+        }
+        event => panic!("unexpected event {event:?}"),
+    }
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn performs_read_command() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_endianness(Endianness::Little);
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::Read {
+        space: AddressSpace::kernel(),
+        addr: 0x600df00d,
+        nbytes: 20,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    let mut buf = [0u8; 32];
+    info!("Awaiting serial");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mr kernel 0x600df00d 20\n"
+    );
+
+    model_tx
+        .write_all(
+            b"600df00d: 5a 5a 5a 5a  01 02 03 04  05 06 07 08  09 0a 0b 0c |ZZZZ............|\n",
+        )
+        .await
+        .unwrap();
+    info!("Awaiting events");
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Read {
+            space: AddressSpace::kernel(),
+            addr: 0x600df00d,
+            data: 0x5a5a5a5a,
+        }
+    );
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Read {
+            space: AddressSpace::kernel(),
+            addr: 0x600df011,
+            data: 0x04030201,
+        }
+    );
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Read {
+            space: AddressSpace::kernel(),
+            addr: 0x600df015,
+            data: 0x08070605,
+        }
+    );
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Read {
+            space: AddressSpace::kernel(),
+            addr: 0x600df019,
+            data: 0x0c0b0a09,
+        }
+    );
+    model_tx
+        .write_all(b"600df01d: 0d 0e 0f 10                                        |....|\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Read {
+            space: AddressSpace::kernel(),
+            addr: 0x600df01d,
+            data: 0x100f0e0d
+        }
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn accesses_other_address_spaces() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf).unwrap();
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let space = AddressSpace::new("telemetry");
+    info!("Sending write");
+    command_tx
+        .send(Command::Write {
+            space: space.clone(),
+            addr: 0x10000,
+            data: 0xa5,
+            width: Width::Word,
+        })
+        .await
+        .unwrap();
+    expect_serial(&mut model_rx, "mw telemetry 0x10000 0xa5\n").await;
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Write {
+            space: space.clone(),
+            addr: 0x10000,
+            data: 0xa5,
+            width: Width::Word,
+        }
+    );
+
+    info!("Sending read");
+    command_tx
+        .send(Command::Read {
+            space: space.clone(),
+            addr: 0x10000,
+            nbytes: 4,
+        })
+        .await
+        .unwrap();
+    expect_serial(&mut model_rx, "mr telemetry 0x10000 4\n").await;
+    model_tx
+        .write_all(b"00010000: 00 00 00 a5 |....|\nDEBUG> ")
+        .await
+        .unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Read {
+            space,
+            addr: 0x10000,
+            data: 0xa5
+        }
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn tolerates_noisy_console() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf).unwrap();
+    let statistics = dap.statistics();
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (_model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending line noise and colored serial prompt");
+    model_tx
+        .write_all(b"\xff\xfe noise\x07\n\x1b[1;32mDEBUG>\x1b[0m ")
+        .await
+        .unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x600df00d,
+        data: 0xa5a5a5a5,
+        width: Width::Word,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    info!("Awaiting event");
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x600df00d,
+            data: 0xa5a5a5a5,
+            width: Width::Word,
+        }
+    );
+    assert_eq!(statistics.get().corrupted_bytes, 2);
+    assert_eq!(statistics.get().discarded_bytes, 12);
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn retries_corrupted_read() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_read_retries(1);
+    let statistics = dap.statistics();
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::Read {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        nbytes: 4,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    let mut buf = [0u8; 32];
+    info!("Awaiting serial");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mr kernel 0x1000 4\n"
+    );
+
+    info!("Sending corrupted response");
+    model_tx
+        .write_all(b"1000: 41 42 43 44 |ABCE|\nDEBUG> ")
+        .await
+        .unwrap();
+
+    info!("Awaiting retry");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mr kernel 0x1000 4\n"
+    );
+    assert_eq!(statistics.get().corrupted_responses, 1);
+
+    model_tx
+        .write_all(b"1000: 41 42 43 44 |ABCD|\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Read {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            data: 0x41424344,
+        }
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn gives_up_read_refused_by_target() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf).unwrap();
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::Read {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        nbytes: 4,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    let mut buf = [0u8; 32];
+    info!("Awaiting serial");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mr kernel 0x1000 4\n"
+    );
+
+    info!("Refusing command");
+    model_tx
+        .write_all(b"invalid address\nDEBUG> ")
+        .await
+        .unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::ReadError {
+            addr: 0x1000,
+            nbytes: 4
+        }
+    );
+
+    // The next command is sent once the prompt returns
+    let command = Command::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x2000,
+        data: 0x1,
+        width: Width::Word,
+    };
+    command_tx.send(command).await.unwrap();
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mw kernel 0x2000 0x1\n"
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn times_out_waiting_for_response() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_response_timeout(Duration::from_millis(200));
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::Read {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        nbytes: 4,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    let mut buf = [0u8; 32];
+    info!("Awaiting serial");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mr kernel 0x1000 4\n"
+    );
+
+    info!("Awaiting timeout");
+    let event = time::timeout(Duration::from_secs(2), event_rx.recv())
+        .await
+        .unwrap();
+    assert_eq!(
+        event.unwrap(),
+        Event::ReadError {
+            addr: 0x1000,
+            nbytes: 4
+        }
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn recovers_from_lost_prompt() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_response_timeout(Duration::from_millis(200));
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let write = |addr| Command::Write {
+        space: AddressSpace::kernel(),
+        addr,
+        data: 0x1,
+        width: Width::Word,
+    };
+    let written = |addr| Event::Write {
+        space: AddressSpace::kernel(),
+        addr,
+        data: 0x1,
+        width: Width::Word,
+    };
+
+    info!("Sending write whose prompt is dropped");
+    command_tx.send(write(0x1000)).await.unwrap();
+    let mut buf = [0u8; 32];
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mw kernel 0x1000 0x1\n"
+    );
+    assert_eq!(event_rx.recv().await.unwrap(), written(0x1000));
+
+    info!("Sending command held for the prompt");
+    let read = Command::Read {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        nbytes: 4,
+    };
+    command_tx.send(read.clone()).await.unwrap();
+    let event = time::timeout(Duration::from_secs(2), event_rx.recv())
+        .await
+        .unwrap();
+    assert_eq!(event.unwrap(), Event::PromptTimeout { command: read });
+
+    info!("Answering request for a fresh prompt");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(std::str::from_utf8(&buf[..n]).unwrap(), "\n");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+
+    info!("Sending write after the timeout");
+    command_tx.send(write(0x2000)).await.unwrap();
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mw kernel 0x2000 0x1\n"
+    );
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    assert_eq!(event_rx.recv().await.unwrap(), written(0x2000));
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn answers_pager_prompt() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf).unwrap();
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"DEBUG> ").await.unwrap();
+    time::sleep(Duration::from_millis(500)).await;
+
+    let command = Command::Read {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        nbytes: 8,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    let mut buf = [0u8; 32];
+    info!("Awaiting serial");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mr kernel 0x1000 8\n"
+    );
+
+    info!("Sending paged response");
+    model_tx
+        .write_all(b"1000: 41 42 43 44 |ABCD|\n--More--")
+        .await
+        .unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Read {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            data: 0x41424344,
+        }
+    );
+
+    info!("Awaiting pager continue");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(std::str::from_utf8(&buf[..n]).unwrap(), " ");
+
+    model_tx
+        .write_all(b"\r        \r1004: 45 46 47 48 |EFGH|\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Read {
+            space: AddressSpace::kernel(),
+            addr: 0x1004,
+            data: 0x45464748,
+        }
+    );
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
+
+#[tokio::test]
+async fn logs_in_before_commands() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let fixture = setup_virtual_serial_ports().await;
+
+    let dap = UartDap::new(fixture.port_a, 115200, Echo::Local, LineEnding::Lf)
+        .unwrap()
+        .with_credentials(Credentials::new("root", "secret"));
+    let model = tokio_serial::new(fixture.port_b, 115200)
+        .open_native_async()
+        .unwrap();
+    let (mut model_rx, mut model_tx) = tokio::io::split(model);
+
+    let (command_tx, command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+
+    let join_handle = tokio::spawn(async move { dap.run(command_rx, event_tx).await.unwrap() });
+
+    let mut buf = [0u8; 32];
+    info!("Awaiting wake up");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(std::str::from_utf8(&buf[..n]).unwrap(), "\n");
+
+    let command = Command::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x600df00d,
+        data: 0xa5a5a5a5,
+        width: Width::Word,
+    };
+    info!("Sending command");
+    command_tx.send(command).await.unwrap();
+
+    for (prompt, expected) in [("target login: ", "root\n"), ("Password: ", "secret\n")] {
+        info!(prompt, "Sending login prompt");
+        model_tx.write_all(prompt.as_bytes()).await.unwrap();
+        let n = model_rx.read(&mut buf).await.unwrap();
+        assert_eq!(std::str::from_utf8(&buf[..n]).unwrap(), expected);
+    }
+
+    info!("Sending serial prompt");
+    model_tx.write_all(b"\nDEBUG> ").await.unwrap();
+
+    info!("Awaiting serial");
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "mw kernel 0x600df00d 0xa5a5a5a5\n"
+    );
+    assert_eq!(
+        event_rx.recv().await.unwrap(),
+        Event::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x600df00d,
+            data: 0xa5a5a5a5,
+            width: Width::Word,
+        }
+    );
+
+    info!("Resetting target");
+    model_tx
+        .write_all(b"\nBooting...\ntarget login: ")
+        .await
+        .unwrap();
+    let n = model_rx.read(&mut buf).await.unwrap();
+    assert_eq!(std::str::from_utf8(&buf[..n]).unwrap(), "root\n");
+
+    if join_handle.is_finished() {
+        join_handle.await.unwrap();
+    } else {
+        join_handle.abort();
+    }
+}
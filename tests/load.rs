@@ -0,0 +1,63 @@
+mod common;
+
+use uart_dap::{Endianness, Loader, Segment};
+
+use common::FakeTarget;
+
+// Memory is zero until written, and writes to `stuck_addr` are ignored. Little endian so that
+// the partial word read back at the end of a segment depends on the byte order of the handle.
+fn fake_target(stuck_addr: u64) -> FakeTarget {
+    FakeTarget::new(Endianness::Little).with_stuck_addr(stuck_addr)
+}
+
+fn segments() -> Vec<Segment> {
+    vec![
+        Segment::new(0x1000, (0..6).collect()),
+        Segment::new(0x2000, (0..10).collect()),
+    ]
+}
+
+#[tokio::test]
+async fn loads_segments() {
+    let target = fake_target(u64::MAX);
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+
+    let mut progress = Vec::new();
+    let report = Loader::new()
+        .with_verify(true)
+        .load(&mut dap, &segments(), |done, total| {
+            progress.push((done, total))
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(progress, [(6, 16), (16, 16)]);
+    assert_eq!(report.bytes_written(), 16);
+    assert_eq!(
+        report
+            .segments
+            .iter()
+            .map(|segment| (segment.addr, segment.bytes_written, segment.verified))
+            .collect::<Vec<_>>(),
+        [(0x1000, 6, Some(true)), (0x2000, 10, Some(true))]
+    );
+    assert!(report.is_verified());
+}
+
+#[tokio::test]
+async fn reports_failed_verification() {
+    let target = fake_target(0x2009);
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+
+    let report = Loader::new()
+        .with_verify(true)
+        .load(&mut dap, &segments(), |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(report.segments[0].verified, Some(true));
+    assert_eq!(report.segments[1].verified, Some(false));
+    assert!(!report.is_verified());
+}
@@ -0,0 +1,173 @@
+use uart_dap::{Access, AccessPolicy, AddressSpace, Command, Rule, Violation, Width};
+
+fn policy() -> AccessPolicy {
+    AccessPolicy::deny_all()
+        .with_allowed(0x1000, 0x1000)
+        .with_read_only(0x1800, 0x100)
+        .with_rule(Rule {
+            space: AddressSpace::kernel(),
+            addr: 0x1c00,
+            len: 0x10,
+            access: Access::ReadWrite,
+            width: Some(Width::Word),
+        })
+}
+
+#[test]
+fn allows_accesses_within_rules() {
+    let policy = policy();
+    for command in [
+        Command::Read {
+            space: AddressSpace::kernel(),
+            addr: 0x1000,
+            nbytes: 0x1000,
+        },
+        Command::Write {
+            space: AddressSpace::kernel(),
+            addr: 0x1c04,
+            data: 1,
+            width: Width::Word,
+        },
+        Command::Fill {
+            space: AddressSpace::kernel(),
+            addr: 0x1c00,
+            len: 0x10,
+            pattern: 0,
+            width: Width::Word,
+        },
+        Command::Copy {
+            space: AddressSpace::kernel(),
+            src: 0x1800,
+            dst: 0x1000,
+            len: 0x100,
+        },
+    ] {
+        assert_eq!(policy.check(&command), None, "{command}");
+    }
+}
+
+#[test]
+fn reports_first_violation() {
+    let policy = policy();
+    let cases = [
+        (
+            Command::Read {
+                space: AddressSpace::kernel(),
+                addr: 0xffc,
+                nbytes: 8,
+            },
+            Violation::Denied { addr: 0xffc },
+        ),
+        (
+            Command::WriteBlock {
+                space: AddressSpace::kernel(),
+                addr: 0x17fc,
+                data: vec![0; 8],
+            },
+            Violation::ReadOnly { addr: 0x1800 },
+        ),
+        (
+            Command::Copy {
+                space: AddressSpace::kernel(),
+                src: 0x1000,
+                dst: 0x1800,
+                len: 4,
+            },
+            Violation::ReadOnly { addr: 0x1800 },
+        ),
+        (
+            Command::Read {
+                space: AddressSpace::kernel(),
+                addr: 0x1c02,
+                nbytes: 4,
+            },
+            Violation::Width {
+                addr: 0x1c02,
+                width: Width::Word,
+            },
+        ),
+        (
+            Command::Fill {
+                space: AddressSpace::kernel(),
+                addr: 0x1c00,
+                len: 0x10,
+                pattern: 0,
+                width: Width::Byte,
+            },
+            Violation::Width {
+                addr: 0x1c00,
+                width: Width::Word,
+            },
+        ),
+    ];
+    for (command, violation) in cases {
+        assert_eq!(policy.check(&command), Some(violation), "{command}");
+    }
+}
+
+#[test]
+fn applies_later_rules_first() {
+    let policy = AccessPolicy::allow_all()
+        .with_denied(0x0, 0x1_0000)
+        .with_allowed(0x8000, 0x100);
+    let command = Command::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x8000,
+        data: 1,
+        width: Width::Word,
+    };
+    assert_eq!(policy.check(&command), None);
+    let command = Command::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x80fe,
+        data: 1,
+        width: Width::Word,
+    };
+    assert_eq!(
+        policy.check(&command),
+        Some(Violation::Denied { addr: 0x8100 })
+    );
+}
+
+#[test]
+fn keys_rules_on_address_space() {
+    let task = AddressSpace::new("task");
+    let policy = AccessPolicy::allow_all()
+        .with_denied(0x1000, 0x100)
+        .with_rule(Rule {
+            space: task.clone(),
+            addr: 0x2000,
+            len: 0x100,
+            access: Access::ReadOnly,
+            width: None,
+        });
+
+    let write = |space: &AddressSpace, addr| Command::Write {
+        space: space.clone(),
+        addr,
+        data: 1,
+        width: Width::Word,
+    };
+    assert_eq!(
+        policy.check(&write(&AddressSpace::kernel(), 0x1000)),
+        Some(Violation::Denied { addr: 0x1000 })
+    );
+    assert_eq!(policy.check(&write(&task, 0x1000)), None);
+    assert_eq!(
+        policy.check(&write(&task, 0x2000)),
+        Some(Violation::ReadOnly { addr: 0x2000 })
+    );
+    assert_eq!(policy.check(&write(&AddressSpace::kernel(), 0x2000)), None);
+
+    let fill_in_task = Command::Fill {
+        space: task,
+        addr: 0x20fc,
+        len: 8,
+        pattern: 0,
+        width: Width::Word,
+    };
+    assert_eq!(
+        policy.check(&fill_in_task),
+        Some(Violation::ReadOnly { addr: 0x20fc })
+    );
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+mod common;
+
+use tokio::sync::mpsc;
+use uart_dap::{Command, Dialect, Endianness, Event, SampleFormat, Sampler};
+
+use common::FakeTarget;
+
+// Holds `addr + 1` in the word at every address, and fails every read of 0x2000
+fn spawn_target() -> (mpsc::Sender<Command>, mpsc::Receiver<Event>) {
+    FakeTarget::new(Endianness::Big)
+        .with_contents(|addr| (addr as u32 / 4 * 4 + 1).to_be_bytes()[addr as usize % 4])
+        .with_read_errors(0x2000, u32::MAX)
+        .spawn()
+}
+
+#[tokio::test]
+async fn writes_csv_rows() {
+    let (command_tx, mut event_rx) = spawn_target();
+    let sampler = Sampler::new(vec![0x1000, 0x2000], Duration::from_millis(10));
+
+    let mut output = Vec::new();
+    let report = sampler
+        .run(&command_tx, &mut event_rx, &mut output, 3)
+        .await
+        .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "timestamp,0x1000,0x2000");
+    for line in &lines[1..] {
+        let (timestamp, values) = line.split_once(',').unwrap();
+        assert!(timestamp.parse::<f64>().unwrap() > 0.0);
+        assert_eq!(values, "0x00001001,");
+    }
+
+    assert_eq!(report.samples, 3);
+    assert_eq!(report.read_errors, 3);
+    assert_eq!(report.requested_rate, 100.0);
+    assert!(report.achieved_rate > 0.0);
+    assert_eq!(report.max_rate, None);
+}
+
+#[tokio::test]
+async fn writes_json_lines() {
+    let (command_tx, mut event_rx) = spawn_target();
+    let sampler = Sampler::new(vec![0x1000, 0x2000], Duration::from_millis(10))
+        .with_format(SampleFormat::JsonLines);
+
+    let mut output = Vec::new();
+    sampler
+        .run(&command_tx, &mut event_rx, &mut output, 1)
+        .await
+        .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let (timestamp, values) = output
+        .trim_end()
+        .strip_prefix("{\"timestamp\":")
+        .unwrap()
+        .split_once(',')
+        .unwrap();
+    assert!(timestamp.parse::<f64>().unwrap() > 0.0);
+    assert_eq!(values, "\"values\":{\"0x1000\":4097,\"0x2000\":null}}");
+}
+
+#[tokio::test]
+async fn reports_max_rate_of_console() {
+    let (command_tx, mut event_rx) = spawn_target();
+    let sampler = Sampler::new(vec![0x1000], Duration::from_millis(10))
+        .with_console(115200, Dialect::integrity());
+
+    let report = sampler
+        .run(&command_tx, &mut event_rx, std::io::sink(), 1)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        report.max_rate,
+        Some(sampler.max_rate(115200, &Dialect::integrity()))
+    );
+}
+
+#[test]
+fn estimates_max_rate_from_baud_rate() {
+    let sampler = Sampler::new(vec![0x1000], Duration::from_millis(1));
+    let max_rate = sampler.max_rate(115200, &Dialect::integrity());
+    // About 100 characters per sample at 11520 characters per second
+    assert!((100.0..150.0).contains(&max_rate), "{max_rate}");
+    assert!(sampler.requested_rate() > max_rate);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use uart_dap::{Endianness, Snapshot, Width, WordChange};
+
+use common::FakeTarget;
+
+// Unwritten words read as their own address. Little endian so that the diffs only match if the
+// snapshot forms words in the same order as the handle.
+fn fake_target() -> FakeTarget {
+    FakeTarget::new(Endianness::Little)
+        .with_contents(|addr| (addr as u32 / 4 * 4).to_le_bytes()[addr as usize % 4])
+}
+
+#[tokio::test]
+async fn diffs_and_restores() {
+    let target = fake_target();
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+    let ranges = [(0x1000, 0x10), (0x2000, 0x8)];
+
+    let before = Snapshot::capture(&mut dap, &ranges).await.unwrap();
+    for (addr, value) in [(0x2004, 0xa5), (0x1008, 0x5a), (0x3000, 1)] {
+        target.write(addr, value, Width::Word);
+    }
+    let after = Snapshot::capture(&mut dap, &ranges).await.unwrap();
+
+    assert_eq!(
+        before.diff(&after),
+        [
+            WordChange {
+                addr: 0x1008,
+                old: 0x1008,
+                new: 0x5a
+            },
+            WordChange {
+                addr: 0x2004,
+                old: 0x2004,
+                new: 0xa5
+            },
+        ]
+    );
+
+    before.restore(&mut dap).await.unwrap();
+    let restored = Snapshot::capture(&mut dap, &ranges).await.unwrap();
+    assert!(before.diff(&restored).is_empty());
+    assert_eq!(target.read(0x3000, Width::Word), 1);
+}
+
+#[tokio::test]
+async fn round_trips_through_serde() {
+    let target = fake_target();
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+    let snapshot = Snapshot::capture(&mut dap, &[(0x1000, 8)]).await.unwrap();
+    assert_eq!(snapshot.endianness, Endianness::Little);
+
+    let json = serde_json::to_string(&snapshot).unwrap();
+    assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+}
+
+#[tokio::test]
+async fn rejects_unaligned_ranges() {
+    let target = fake_target();
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+    let error = Snapshot::capture(&mut dap, &[(0x1002, 8)])
+        .await
+        .unwrap_err();
+    assert_eq!(error.to_string(), "range 0x1002+0x8 is not word aligned");
+}
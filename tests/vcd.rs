@@ -0,0 +1,106 @@
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::mpsc;
+use uart_dap::{AddressSpace, Command, Event, Sampler, Signal, VcdWriter};
+
+const HEADER: &str = "\
+$version uart-dap $end
+$timescale 1us $end
+$scope module uart_dap $end
+$var wire 32 ! ctrl $end
+$var wire 1 \" ctrl_enable $end
+$var wire 4 # ctrl_mode $end
+$upscope $end
+$enddefinitions $end
+";
+
+fn signals() -> Vec<Signal> {
+    vec![
+        Signal::register("ctrl", 0x1000),
+        Signal::bitfield("ctrl_enable", 0x1000, 0, 1).unwrap(),
+        Signal::bitfield("ctrl mode", 0x1000, 4, 4).unwrap(),
+    ]
+}
+
+#[test]
+fn writes_only_changes() {
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let mut vcd = VcdWriter::new(Vec::new(), signals()).unwrap();
+
+    vcd.record_event(&Event::Watched {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        value: 0x31,
+        timestamp: start,
+    })
+    .unwrap();
+    // Unrelated address
+    vcd.record(0x2000, 0x0, start + Duration::from_millis(1))
+        .unwrap();
+    vcd.record_event(&Event::Changed {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        old: 0x31,
+        new: 0x30,
+        timestamp: start + Duration::from_millis(2),
+    })
+    .unwrap();
+    // No change
+    vcd.record(0x1000, 0x30, start + Duration::from_millis(3))
+        .unwrap();
+
+    let output = String::from_utf8(vcd.finish().unwrap()).unwrap();
+    let expected = HEADER.to_string()
+        + "\
+#0
+$dumpvars
+bx !
+x\"
+bx #
+$end
+b110001 !
+1\"
+b11 #
+#2000
+b110000 !
+0\"
+";
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn rejects_bitfields_outside_word() {
+    assert!(Signal::bitfield("empty", 0x1000, 0, 0).is_err());
+    assert!(Signal::bitfield("past_end", 0x1000, 28, 8).is_err());
+    assert!(Signal::bitfield("overflow", 0x1000, u32::MAX, 2).is_err());
+    assert!(Signal::bitfield("top", 0x1000, 28, 4).is_ok());
+}
+
+#[tokio::test]
+async fn records_samples() {
+    let (command_tx, mut command_rx) = mpsc::channel(1);
+    let (event_tx, mut event_rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        while let Some(Command::Read { space, addr, .. }) = command_rx.recv().await {
+            event_tx
+                .send(Event::Read {
+                    space,
+                    addr,
+                    data: 0x1,
+                })
+                .await
+                .unwrap();
+        }
+    });
+
+    let sampler = Sampler::new(vec![0x1000], Duration::from_millis(10));
+    let mut vcd = VcdWriter::new(Vec::new(), signals()).unwrap();
+    sampler
+        .run_vcd(&command_tx, &mut event_rx, &mut vcd, 2)
+        .await
+        .unwrap();
+
+    let output = String::from_utf8(vcd.finish().unwrap()).unwrap();
+    let values = output.strip_prefix(HEADER).unwrap();
+    assert!(values.ends_with("$end\nb1 !\n1\"\nb0 #\n"), "{values}");
+}
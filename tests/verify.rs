@@ -0,0 +1,100 @@
+mod common;
+
+use uart_dap::{Endianness, Mismatch, Segment, Verifier};
+
+use common::{low_byte, FakeTarget};
+
+// Holds the low byte of each address, forming words little endian so that a handle reading them
+// in the wrong byte order would report mismatches
+fn fake_target() -> FakeTarget {
+    FakeTarget::new(Endianness::Little).with_contents(low_byte)
+}
+
+#[tokio::test]
+async fn matches_identical_memory() {
+    let target = fake_target();
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+    let segments = [
+        Segment::new(0x1000, (0x00..0x06).collect()),
+        Segment::new(0x2002, (0x02..0x0c).collect()),
+    ];
+
+    let mut progress = Vec::new();
+    let report = Verifier::new()
+        .verify(&mut dap, &segments, |done, total| {
+            progress.push((done, total))
+        })
+        .await
+        .unwrap();
+
+    assert!(report.is_match());
+    assert_eq!(report.bytes_compared, 16);
+    assert_eq!(progress, [(6, 16), (16, 16)]);
+}
+
+#[tokio::test]
+async fn reports_first_mismatches() {
+    let target = fake_target();
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+    let mut data = (0x00..0x10).collect::<Vec<u8>>();
+    for addr in [0x3, 0x7, 0xe] {
+        data[addr] = 0xff;
+    }
+
+    let report = Verifier::new()
+        .with_max_mismatches(2)
+        .verify(&mut dap, &[Segment::new(0x1000, data)], |_, _| {})
+        .await
+        .unwrap();
+
+    assert!(!report.is_match());
+    assert_eq!(report.mismatch_count, 3);
+    assert_eq!(
+        report.mismatches,
+        [
+            Mismatch {
+                addr: 0x1003,
+                expected: 0xff,
+                actual: 0x03
+            },
+            Mismatch {
+                addr: 0x1007,
+                expected: 0xff,
+                actual: 0x07
+            },
+        ]
+    );
+    assert_eq!(
+        report.mismatches[0].to_string(),
+        "0x00001003: expected 0xff, read 0x03"
+    );
+}
+
+#[tokio::test]
+async fn verifies_to_the_top_of_the_address_space() {
+    let target = fake_target();
+    let (command_tx, mut event_rx) = target.spawn();
+    let mut dap = target.handle(&command_tx, &mut event_rx);
+    let mut data = (0xf0..=0xff).collect::<Vec<u8>>();
+    data[0xf] = 0;
+
+    let report = Verifier::new()
+        .verify(
+            &mut dap,
+            &[Segment::new(0xffff_ffff_ffff_fff0, data)],
+            |_, _| {},
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        report.mismatches,
+        [Mismatch {
+            addr: u64::MAX,
+            expected: 0,
+            actual: 0xff,
+        }]
+    );
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use uart_dap::{AddressSpace, Command, Dialect, Endianness, Event, LineEnding, UartDap, Width};
+
+use common::{run, Transcript};
+
+#[tokio::test]
+async fn pads_addresses_to_the_dialect_width() {
+    let transcript = Transcript::default();
+    let dialect = Dialect {
+        address_digits: 16,
+        ..Dialect::integrity()
+    };
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::Lf).with_dialect(dialect);
+
+    let commands_and_events = vec![
+        (
+            Command::Read {
+                space: AddressSpace::kernel(),
+                addr: 0x1_0000_1000,
+                nbytes: 4,
+            },
+            vec![Event::Read {
+                space: AddressSpace::kernel(),
+                addr: 0x1_0000_1000,
+                data: 0,
+            }],
+        ),
+        (
+            Command::Write {
+                space: AddressSpace::kernel(),
+                addr: 0xffff_0000_0000_0004,
+                data: 0xa5,
+                width: Width::Word,
+            },
+            vec![Event::Write {
+                space: AddressSpace::kernel(),
+                addr: 0xffff_0000_0000_0004,
+                data: 0xa5,
+                width: Width::Word,
+            }],
+        ),
+    ];
+    run(dap, commands_and_events).await;
+
+    assert_eq!(
+        transcript.text(),
+        "mr kernel 0x0000000100001000 4\nmw kernel 0xffff000000000004 0xa5\n"
+    );
+}
+
+#[tokio::test]
+async fn fills_doublewords_on_uboot() {
+    let transcript = Transcript::default();
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::Lf).with_dialect(Dialect::uboot());
+
+    let commands_and_events = vec![(
+        Command::Fill {
+            space: AddressSpace::kernel(),
+            addr: 0x1_2000_0000,
+            len: 16,
+            pattern: 0x1122_3344_5566_7788,
+            width: Width::Doubleword,
+        },
+        vec![Event::Fill {
+            addr: 0x1_2000_0000,
+            len: 16,
+        }],
+    )];
+    run(dap, commands_and_events).await;
+
+    assert_eq!(
+        transcript.text(),
+        "mw.q 0x120000000 0x1122334455667788 0x2\n"
+    );
+}
+
+#[tokio::test]
+async fn writes_doublewords_in_one_command() {
+    let doubleword = Command::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x1_0000_1000,
+        data: 0x1122_3344_5566_7788,
+        width: Width::Doubleword,
+    };
+    let written = Event::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x1_0000_1000,
+        data: 0x1122_3344_5566_7788,
+        width: Width::Doubleword,
+    };
+
+    let transcript = Transcript::default();
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::Lf);
+    run(dap, vec![(doubleword.clone(), vec![written.clone()])]).await;
+    assert_eq!(
+        transcript.text(),
+        "mw kernel 0x100001000 0x1122334455667788\n"
+    );
+
+    let transcript = Transcript::default();
+    let dap = UartDap::dry_run(transcript.clone(), LineEnding::Lf).with_dialect(Dialect::uboot());
+    run(dap, vec![(doubleword, vec![written])]).await;
+    assert_eq!(transcript.text(), "mw.q 0x100001000 0x1122334455667788\n");
+}
+
+#[tokio::test]
+async fn echoes_small_doublewords_as_doublewords() {
+    let dialect = Dialect::integrity();
+    let command = Command::Write {
+        space: AddressSpace::kernel(),
+        addr: 0x1000,
+        data: 1,
+        width: Width::Doubleword,
+    };
+    let text = dialect.format_command(&command, Endianness::Big);
+    assert_eq!(text, "mw kernel 0x1000 0x0000000000000001");
+
+    let tokens = text.split_whitespace().collect::<Vec<_>>();
+    assert_eq!(
+        dialect.parse_command(&tokens, Endianness::Big),
+        Some(command)
+    );
+}